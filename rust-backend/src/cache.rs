@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Default upper bound on the on-disk cache size (2 GB)
+const DEFAULT_MAX_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Entries looked up or added this recently may be about to run (their judge has yet
+/// to `pin` them), so eviction and `clear` leave them alone
+const IN_USE_GRACE: Duration = Duration::from_secs(60);
+
+/// Language tags of cached binaries, as in `<sha1>_<tag>.exe`
+const LANGUAGE_TAGS: &[&str] = &["c", "cpp", "rs"];
+
+/// Extensions of the files making up an entry: the binary and its sidecars
+const ENTRY_EXTENSIONS: &[&str] = &["exe", "warnings", "build_ms"];
+
+/// Entry stem (`<sha1>_<tag>`) of a file named like one the cache writes, or `None` for
+/// anything else, which is never counted or deleted; the directory may be shared.
+/// A binary still being copied in (`.partial-<pid>-<n>`) belongs to its entry.
+pub(crate) fn entry_stem(path: &Path) -> Option<&str> {
+    let (stem, extension) = path.file_name()?.to_str()?.split_once('.')?;
+    let (hash, tag) = stem.split_once('_')?;
+    let hashed = hash.len() == 40 && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    let partial = extension
+        .strip_prefix("partial-")
+        .is_some_and(|nonce| !nonce.is_empty() && nonce.bytes().all(|b| b.is_ascii_digit() || b == b'-'));
+    (hashed && LANGUAGE_TAGS.contains(&tag) && (ENTRY_EXTENSIONS.contains(&extension) || partial)).then_some(stem)
+}
+
+/// Remove the files in `dir` named `<prefix>*` that were last modified over `max_age` ago
+pub(crate) fn remove_old_files(dir: &Path, prefix: &str, max_age: Duration) -> GcReport {
+    let mut report = GcReport::default();
+    let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(SystemTime::UNIX_EPOCH);
+    let Ok(entries) = std::fs::read_dir(dir) else { return report };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.file_name().and_then(|n| n.to_str()).is_some_and(|name| name.starts_with(prefix)) {
+            continue;
+        }
+        if let Ok(meta) = entry.metadata() {
+            if meta.is_file() && meta.modified().is_ok_and(|modified| modified < cutoff) && std::fs::remove_file(&path).is_ok() {
+                report.record(meta.len());
+            }
+        }
+    }
+    report
+}
+
+/// Whether an entry last used at `last_use` may still be in use
+fn in_use(last_use: SystemTime) -> bool {
+    SystemTime::now().duration_since(last_use).map_or(true, |age| age < IN_USE_GRACE)
+}
+
+/// Files removed by a cleanup pass
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct GcReport {
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+}
+
+impl GcReport {
+    /// Account for one removed file of `bytes`
+    pub(crate) fn record(&mut self, bytes: u64) {
+        self.removed_files += 1;
+        self.freed_bytes += bytes;
+    }
+
+    /// Combine the results of two passes
+    pub fn merge(self, other: GcReport) -> GcReport {
+        GcReport {
+            removed_files: self.removed_files + other.removed_files,
+            freed_bytes: self.freed_bytes + other.freed_bytes,
+        }
+    }
+}
+
+/// On-disk cache of compiled binaries with size-bounded LRU eviction.
+///
+/// An entry is every file sharing a stem (`<sha1>_<lang>.exe` plus the `.warnings` and
+/// `.build_ms` sidecars); other files in the directory are ignored. Recency is tracked through the binary's mtime, which is
+/// refreshed on every hit.
+#[derive(Debug, Clone)]
+pub struct CompileCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Lookup outcomes since startup, shared by all clones
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+    /// Holders of each pinned entry, by stem, shared by all clones
+    pinned: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+/// Keeps a cache entry from being evicted or cleared while held, however long the
+/// judge running it takes; pins of files outside the cache do nothing
+#[derive(Debug)]
+#[must_use]
+pub struct CachePin {
+    pinned: Arc<Mutex<HashMap<String, usize>>>,
+    stem: Option<String>,
+}
+
+impl Drop for CachePin {
+    fn drop(&mut self) {
+        let Some(stem) = &self.stem else { return };
+        let mut pinned = self.pinned.lock().unwrap();
+        if let Some(holders) = pinned.get_mut(stem) {
+            *holders -= 1;
+            if *holders == 0 {
+                pinned.remove(stem);
+            }
+        }
+    }
+}
+
+/// Snapshot of the cache's contents and hit rate
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct CacheUsage {
+    pub bytes: u64,
+    pub binaries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// What the cache holds right now, by entry (a binary and its sidecars)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CacheStats {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub bytes: u64,
+    /// Last use of the least recently used entry
+    pub oldest: Option<SystemTime>,
+    /// Last use of the most recently used entry
+    pub newest: Option<SystemTime>,
+}
+
+impl Default for CompileCache {
+    /// `DSA_JUDGE_CACHE_DIR`, or the platform cache dir, capped by
+    /// `DSA_JUDGE_CACHE_MAX_BYTES` (default 2 GB)
+    fn default() -> Self {
+        let dir = std::env::var_os("DSA_JUDGE_CACHE_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| dirs::cache_dir().unwrap_or(std::env::temp_dir()).join("dsa_judge_cache"));
+        let max_bytes = std::env::var("DSA_JUDGE_CACHE_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        Self::new(dir, max_bytes)
+    }
+}
+
+impl CompileCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self { dir: dir.into(), max_bytes, hits: Arc::default(), misses: Arc::default(), pinned: Arc::default() }
+    }
+
+    /// Pin the entry holding the binary at `path` until the returned guard is dropped
+    pub fn pin(&self, path: &Path) -> CachePin {
+        let stem = entry_stem(path).filter(|_| path.parent() == Some(self.dir.as_path())).map(str::to_string);
+        if let Some(stem) = &stem {
+            *self.pinned.lock().unwrap().entry(stem.clone()).or_default() += 1;
+        }
+        CachePin { pinned: Arc::clone(&self.pinned), stem }
+    }
+
+    /// Whether the entry `stem`, last used at `last_use`, may be deleted
+    fn evictable(&self, stem: &str, last_use: SystemTime) -> bool {
+        !in_use(last_use) && !self.pinned.lock().unwrap().contains_key(stem)
+    }
+
+    /// Directory holding the cached files
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of a cache file by name (whether or not it exists)
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Look up a cached binary, marking it as recently used
+    pub fn lookup(&self, name: &str) -> Option<PathBuf> {
+        let path = self.path(name);
+        if !path.exists() {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        // Read-only handle: a binary that is currently executing can't be opened for writing
+        if let Ok(file) = std::fs::File::open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(path)
+    }
+
+    /// Size on disk and entry count (sidecars belong to their binary), plus lookup counters
+    pub fn usage(&self) -> CacheUsage {
+        let entries = self.entries();
+        CacheUsage {
+            bytes: entries.values().map(|e| e.1).sum(),
+            binaries: entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Entry count, size and the range of last-use times
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries();
+        CacheStats {
+            dir: self.dir.clone(),
+            entries: entries.len(),
+            bytes: entries.values().map(|e| e.1).sum(),
+            oldest: entries.values().map(|e| e.2).min(),
+            newest: entries.values().map(|e| e.2).max(),
+        }
+    }
+
+    /// Delete every entry that is neither pinned nor used within `IN_USE_GRACE`, returning
+    /// how many were removed; stops at the first file that can't be deleted
+    pub fn clear(&self) -> std::io::Result<usize> {
+        if let Err(e) = std::fs::read_dir(&self.dir) {
+            return if e.kind() == std::io::ErrorKind::NotFound { Ok(0) } else { Err(e) };
+        }
+        let entries: Vec<_> = self.entries().into_iter().filter(|(stem, e)| self.evictable(stem, e.2)).collect();
+        let count = entries.len();
+        for file in entries.into_iter().flat_map(|(_, e)| e.0) {
+            match std::fs::remove_file(&file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(count)
+    }
+
+    /// Copy a freshly built binary into the cache, evicting old entries to make room.
+    /// The copy is renamed into place, so a concurrent lookup of the same build never
+    /// runs a half-written binary
+    pub fn insert(&self, name: &str, source: &Path) -> std::io::Result<PathBuf> {
+        static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(0);
+        std::fs::create_dir_all(&self.dir)?;
+        let incoming = std::fs::metadata(source)?.len();
+        self.evict_to_fit(incoming);
+        let path = self.path(name);
+        let stem = name.split_once('.').map_or(name, |(stem, _)| stem);
+        let nonce = NEXT_PARTIAL.fetch_add(1, Ordering::Relaxed);
+        let partial = self.path(&format!("{}.partial-{}-{}", stem, std::process::id(), nonce));
+        let copied = std::fs::copy(source, &partial).and_then(|_| std::fs::rename(&partial, &path));
+        if let Err(e) = copied {
+            let _ = std::fs::remove_file(&partial);
+            // Windows won't replace a binary that is running; the other copy is just as good
+            if !path.exists() {
+                return Err(e);
+            }
+        }
+        Ok(path)
+    }
+
+    /// Remove entries unused for longer than `max_age`, then enforce the size cap
+    pub fn remove_stale(&self, max_age: Duration) -> GcReport {
+        let cutoff = SystemTime::now().checked_sub(max_age).unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut report = GcReport::default();
+        for (stem, (files, _, last_use)) in self.entries() {
+            if last_use < cutoff && self.evictable(&stem, last_use) {
+                remove_files(files, &mut report);
+            }
+        }
+        report.merge(self.evict_to_fit(0))
+    }
+
+    /// Remove least-recently-used entries until `incoming` more bytes fit under the cap;
+    /// pinned or just-used entries are kept even if that leaves the cache over it
+    fn evict_to_fit(&self, incoming: u64) -> GcReport {
+        let entries = self.entries();
+        let mut report = GcReport::default();
+        let mut total: u64 = entries.values().map(|e| e.1).sum();
+        let mut by_age: Vec<_> = entries.into_iter().filter(|(stem, e)| self.evictable(stem, e.2)).map(|(_, e)| e).collect();
+        by_age.sort_by_key(|e| e.2);
+        for (files, size, _) in by_age {
+            if total + incoming <= self.max_bytes {
+                break;
+            }
+            remove_files(files, &mut report);
+            total = total.saturating_sub(size);
+        }
+        report
+    }
+
+    /// Cached entries by stem: (files, total bytes, last use)
+    fn entries(&self) -> HashMap<String, (Vec<PathBuf>, u64, SystemTime)> {
+        let mut entries: HashMap<String, (Vec<PathBuf>, u64, SystemTime)> = HashMap::new();
+        let Ok(read_dir) = std::fs::read_dir(&self.dir) else { return entries };
+        for file in read_dir.flatten() {
+            let path = file.path();
+            let (Some(stem), Ok(meta)) = (entry_stem(&path), file.metadata()) else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
+            }
+            let entry = entries
+                .entry(stem.to_string())
+                .or_insert_with(|| (Vec::new(), 0, SystemTime::UNIX_EPOCH));
+            entry.1 += meta.len();
+            if let Ok(modified) = meta.modified() {
+                entry.2 = entry.2.max(modified);
+            }
+            entry.0.push(path);
+        }
+        entries
+    }
+}
+
+/// Delete `files`, counting the ones actually removed
+fn remove_files(files: Vec<PathBuf>, report: &mut GcReport) {
+    for file in files {
+        let bytes = std::fs::metadata(&file).map_or(0, |m| m.len());
+        if std::fs::remove_file(&file).is_ok() {
+            report.record(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Cache file name for entry `id` with `extension`, shaped like `Compiler` writes them
+    fn entry(id: u8, extension: &str) -> String {
+        format!("{:040x}_cpp.{}", id, extension)
+    }
+
+    /// Write `bytes` bytes to `dir/name`, last modified `age` ago
+    fn file_aged(dir: &Path, name: &str, bytes: usize, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; bytes]).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    }
+
+    #[test]
+    fn remove_stale_drops_old_entries_with_their_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(dir.path(), u64::MAX);
+        let stale = file_aged(dir.path(), &entry(1, "exe"), 10, 30 * DAY);
+        let sidecar = file_aged(dir.path(), &entry(1, "warnings"), 5, 30 * DAY);
+        let fresh = file_aged(dir.path(), &entry(2, "exe"), 10, Duration::ZERO);
+
+        let report = cache.remove_stale(7 * DAY);
+        assert_eq!((report.removed_files, report.freed_bytes), (2, 15));
+        assert!(!stale.exists() && !sidecar.exists());
+        assert!(fresh.exists());
+    }
+
+    #[test]
+    fn remove_stale_trims_to_the_size_cap_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(dir.path(), 25);
+        let oldest = file_aged(dir.path(), &entry(1, "exe"), 10, 3 * DAY);
+        let older = file_aged(dir.path(), &entry(2, "exe"), 10, 2 * DAY);
+        let newest = file_aged(dir.path(), &entry(3, "exe"), 10, DAY);
+
+        let report = cache.remove_stale(7 * DAY);
+        assert_eq!(report.removed_files, 1);
+        assert!(!oldest.exists());
+        assert!(older.exists() && newest.exists());
+    }
+
+    #[test]
+    fn entries_in_use_survive_eviction_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(dir.path(), 5);
+        let idle = file_aged(dir.path(), &entry(1, "exe"), 10, DAY);
+        let just_used = file_aged(dir.path(), &entry(2, "exe"), 10, Duration::ZERO);
+
+        assert_eq!(cache.remove_stale(Duration::ZERO).removed_files, 1);
+        assert!(!idle.exists() && just_used.exists());
+        assert_eq!(cache.clear().unwrap(), 0);
+        assert!(just_used.exists());
+    }
+
+    #[test]
+    fn pinned_entries_outlive_the_grace_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(dir.path(), 0);
+        let running = file_aged(dir.path(), &entry(1, "exe"), 10, DAY);
+        let pin = cache.pin(&running);
+        let second = cache.pin(&running);
+
+        assert_eq!(cache.remove_stale(Duration::ZERO).removed_files, 0);
+        drop(pin);
+        assert_eq!(cache.clear().unwrap(), 0);
+        drop(second);
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(!running.exists());
+    }
+
+    #[test]
+    fn insert_leaves_only_the_finished_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(dir.path().join("cache"), u64::MAX);
+        let built = file_aged(dir.path(), "solution.exe", 10, Duration::ZERO);
+
+        let path = cache.insert(&entry(1, "exe"), &built).unwrap();
+        assert_eq!(cache.insert(&entry(1, "exe"), &built).unwrap(), path);
+        let names: Vec<_> = std::fs::read_dir(cache.dir()).unwrap().map(|f| f.unwrap().file_name()).collect();
+        assert_eq!(names, [entry(1, "exe").as_str()]);
+        assert_eq!(std::fs::read(path).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn files_the_cache_did_not_write_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(dir.path(), 0);
+        let foreign: Vec<_> = ["notes.txt", "old_cpp.exe", &format!("{:040x}_py.exe", 1), &format!("{:040x}_c.bak", 1)]
+            .into_iter()
+            .map(|name| file_aged(dir.path(), name, 10, 30 * DAY))
+            .collect();
+        let ours = file_aged(dir.path(), &entry(1, "exe"), 10, 30 * DAY);
+
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.remove_stale(Duration::ZERO).removed_files, 0);
+        assert!(!ours.exists());
+        assert!(foreign.iter().all(|path| path.exists()));
+    }
+}
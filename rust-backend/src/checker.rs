@@ -0,0 +1,176 @@
+//! Custom checker ("special judge") support.
+//!
+//! The protocol follows testlib conventions so existing checkers work unchanged:
+//!
+//! * argv: `checker <input-file> <contestant-output-file> <expected-answer-file>`
+//! * exit code `0` accepts the output, `1` (wrong answer) and `2` (presentation
+//!   error) reject it; any other exit code or a crash means the checker itself failed
+//! * on exit code `0`, stdout may contain a score in `0.0..=1.0` as its first token; it
+//!   scales the case's weight. Only a full score is accepted, one strictly between 0 and
+//!   1 is partially correct, and a rejected output earns nothing whatever it prints
+//! * stderr is treated as a human-readable comment and passed back to the caller
+
+use crate::cache::CachePin;
+use crate::comparator::{Comparator, Comparison};
+use crate::compiler::{Artifact, CompileOptions, Compiler};
+use crate::executor::{ProgramInput, SEED_ENV_VAR};
+use crate::types::{CheckerSpec, TestVerdict};
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::time::{timeout, Duration};
+
+/// Verdict reported by a checker for a single test case
+#[derive(Debug, Clone)]
+pub struct CheckerOutcome {
+    pub accepted: bool,
+    pub score: Option<f64>,
+    pub message: Option<String>,
+}
+
+impl CheckerOutcome {
+    /// Fraction of the case's weight earned: nothing unless accepted, then the reported
+    /// score or all of it
+    pub fn points(&self) -> f64 {
+        if self.accepted { self.score.unwrap_or(1.0) } else { 0.0 }
+    }
+
+    /// `Accepted` for full points, `PartiallyCorrect` for some, else `WrongAnswer`
+    pub fn verdict(&self) -> TestVerdict {
+        match self.points() {
+            p if p >= 1.0 => TestVerdict::Accepted,
+            p if p > 0.0 => TestVerdict::PartiallyCorrect,
+            _ => TestVerdict::WrongAnswer,
+        }
+    }
+}
+
+/// A compiled checker, reused across all test cases of a submission
+pub struct Checker {
+    artifact: Artifact,
+    time_limit: Duration,
+    _pin: CachePin,
+}
+
+impl Checker {
+    /// Compile the checker source; the binary goes through the regular compile cache
+    pub async fn compile(compiler: &Compiler, spec: &CheckerSpec) -> Result<Self> {
+        let artifact = compiler
+            .compile_cpp(&spec.source, &CompileOptions::default(), &[])
+            .await
+            .context("Failed to compile checker")?
+            .artifact;
+        Ok(Self {
+            _pin: compiler.pin(&artifact),
+            artifact,
+            time_limit: Duration::from_millis(spec.time_limit_ms.unwrap_or(10_000)),
+        })
+    }
+
+    /// Run the checker against one test case
+    pub async fn check(&self, input: &ProgramInput<'_>, expected: &str, actual: &str) -> Result<CheckerOutcome> {
+        self.check_seeded(input, expected, actual, None).await
+    }
+
+    /// `check` for a generated test case, with its seed in `JUDGE_SEED`
+    pub async fn check_seeded(&self, input: &ProgramInput<'_>, expected: &str, actual: &str, seed: Option<u64>) -> Result<CheckerOutcome> {
+        let dir = TempDir::new().context("Failed to create checker directory")?;
+        let input_path = input.to_path(dir.path().join("input.txt")).await?;
+        let output_path = dir.path().join("output.txt");
+        let answer_path = dir.path().join("answer.txt");
+        tokio::fs::write(&output_path, actual).await?;
+        tokio::fs::write(&answer_path, expected).await?;
+
+        let mut cmd = self.artifact.command();
+        cmd.arg(&input_path)
+            .arg(&output_path)
+            .arg(&answer_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(seed) = seed {
+            cmd.env(SEED_ENV_VAR, seed.to_string());
+        }
+        let output = timeout(self.time_limit, cmd.output())
+            .await
+            .context("Checker timeout")?
+            .context("Failed to run checker")?;
+
+        let accepted = match output.status.code() {
+            Some(0) => true,
+            Some(1) | Some(2) => false,
+            code => {
+                return Err(anyhow::anyhow!(
+                    "Checker failed (exit code {:?}): {}",
+                    code,
+                    String::from_utf8_lossy(&output.stderr)
+                ))
+            }
+        };
+
+        let score = String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .and_then(|tok| tok.parse::<f64>().ok())
+            .filter(|s| (0.0..=1.0).contains(s));
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+        Ok(CheckerOutcome {
+            accepted,
+            score,
+            message: if message.is_empty() { None } else { Some(message) },
+        })
+    }
+}
+
+impl Comparator for Checker {
+    fn compare<'a>(&'a self, input: &'a ProgramInput<'a>, expected: &'a str, actual: &'a str) -> BoxFuture<'a, Comparison> {
+        self.compare_seeded(input, expected, actual, None)
+    }
+
+    /// A checker that fails (crashes, times out) gives a `JudgeError`, with the reason as its message
+    fn compare_seeded<'a>(
+        &'a self,
+        input: &'a ProgramInput<'a>,
+        expected: &'a str,
+        actual: &'a str,
+        seed: Option<u64>,
+    ) -> BoxFuture<'a, Comparison> {
+        Box::pin(async move {
+            match self.check_seeded(input, expected, actual, seed).await {
+                Ok(outcome) => Comparison {
+                    verdict: outcome.verdict(),
+                    points: Some(outcome.points()),
+                    message: outcome.message,
+                    ..Comparison::from_match(false)
+                },
+                Err(e) => Comparison {
+                    verdict: TestVerdict::JudgeError,
+                    message: Some(format!("{:#}", e)),
+                    ..Comparison::from_match(false)
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(accepted: bool, score: Option<f64>) -> CheckerOutcome {
+        CheckerOutcome { accepted, score, message: None }
+    }
+
+    #[test]
+    fn only_accepted_output_earns_points() {
+        assert_eq!(outcome(false, Some(0.5)).points(), 0.0);
+        assert_eq!(outcome(false, Some(0.5)).verdict(), TestVerdict::WrongAnswer);
+        assert_eq!(outcome(true, Some(0.5)).verdict(), TestVerdict::PartiallyCorrect);
+        assert_eq!(outcome(true, Some(0.0)).verdict(), TestVerdict::WrongAnswer);
+        assert_eq!(outcome(true, None).points(), 1.0);
+        assert_eq!(outcome(true, Some(1.0)).verdict(), TestVerdict::Accepted);
+    }
+}
@@ -0,0 +1,186 @@
+//! Output comparison: deciding whether a run that exited cleanly produced a correct answer.
+//!
+//! `NormalizedComparator` (the request's `NormalizationOptions`) is the default and a
+//! problem's custom `Checker` replaces it; embedders can plug in their own through
+//! `Judge::with_comparator`.
+
+use crate::diff::unified_diff;
+use crate::executor::ProgramInput;
+use crate::types::{NormalizationOptions, TestVerdict};
+use futures::future::BoxFuture;
+
+/// Outcome of comparing one run's output against an expected answer
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// `Accepted`, `WrongAnswer`, `PresentationError` or `PartiallyCorrect`; `JudgeError`
+    /// when a checker failed
+    pub verdict: TestVerdict,
+    /// Fraction of the case's weight earned; all or nothing by verdict when unset
+    pub points: Option<f64>,
+    /// Comment for the student, e.g. a checker's stderr
+    pub message: Option<String>,
+    pub first_diff_token: Option<usize>,
+    pub first_diff_line: Option<usize>,
+    pub diff: Option<String>,
+}
+
+impl Comparison {
+    /// `Accepted` or `WrongAnswer`, with no further detail
+    pub fn from_match(matched: bool) -> Self {
+        Self {
+            verdict: if matched { TestVerdict::Accepted } else { TestVerdict::WrongAnswer },
+            points: None,
+            message: None,
+            first_diff_token: None,
+            first_diff_line: None,
+            diff: None,
+        }
+    }
+}
+
+/// Decides whether `actual` is a correct answer for `input`, given the expected answer
+pub trait Comparator: Send + Sync {
+    fn compare<'a>(&'a self, input: &'a ProgramInput<'a>, expected: &'a str, actual: &'a str) -> BoxFuture<'a, Comparison>;
+
+    /// `compare` for a test case with a generator `seed`; comparators that don't need it
+    /// keep this default
+    fn compare_seeded<'a>(
+        &'a self,
+        input: &'a ProgramInput<'a>,
+        expected: &'a str,
+        actual: &'a str,
+        _seed: Option<u64>,
+    ) -> BoxFuture<'a, Comparison> {
+        self.compare(input, expected, actual)
+    }
+}
+
+/// Equality after normalization, token-wise (with optional float tolerance) when asked
+#[derive(Debug, Clone)]
+pub struct NormalizedComparator {
+    options: NormalizationOptions,
+    max_diff_lines: usize,
+}
+
+impl NormalizedComparator {
+    pub fn new(options: NormalizationOptions, max_diff_lines: usize) -> Self {
+        Self { options, max_diff_lines }
+    }
+}
+
+impl Comparator for NormalizedComparator {
+    fn compare<'a>(&'a self, _input: &'a ProgramInput<'a>, expected: &'a str, actual: &'a str) -> BoxFuture<'a, Comparison> {
+        let opts = &self.options;
+        let (mut expected, mut actual) = (normalize(expected, opts), normalize(actual, opts));
+        // Sorted lines are equal exactly when the line multisets are; the diff shows them sorted too
+        if opts.unordered_lines {
+            (expected, actual) = (sort_lines(&expected), sort_lines(&actual));
+        }
+        let mut comparison = Comparison::from_match(false);
+        // Tolerance needs token boundaries, so it implies token mode
+        if opts.token_mode || opts.float_tolerance.is_some() {
+            comparison.first_diff_token = first_token_mismatch(&expected, &actual, opts.float_tolerance);
+            if comparison.first_diff_token.is_none() {
+                return Box::pin(async { Comparison::from_match(true) });
+            }
+        } else if actual == expected {
+            return Box::pin(async { Comparison::from_match(true) });
+        } else if first_token_mismatch(&expected, &actual, None).is_none() {
+            comparison.verdict = TestVerdict::PresentationError;
+        }
+        comparison.first_diff_line = first_line_mismatch(&expected, &actual);
+        comparison.diff = unified_diff(&expected, &actual, self.max_diff_lines);
+        Box::pin(async { comparison })
+    }
+}
+
+/// Whether an expected token gets the float tolerance: a finite number written with a
+/// decimal point or an exponent (`3.0`, `.5`, `1e-9`). Integers (`3`, `-12`) and
+/// everything else must match exactly, so `3` vs `3.0000001` is a wrong answer.
+fn is_float_token(token: &str) -> bool {
+    token.contains(['.', 'e', 'E']) && token.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// Index of the first token that differs, or where one side runs out of tokens.
+/// Float tokens (see `is_float_token`) may differ by `tolerance`, absolute or relative
+/// to the expected value.
+fn first_token_mismatch(expected: &str, actual: &str, tolerance: Option<f64>) -> Option<usize> {
+    let expected_tokens: Vec<&str> = expected.split_whitespace().collect();
+    let actual_tokens: Vec<&str> = actual.split_whitespace().collect();
+    let mismatch = expected_tokens.iter().zip(&actual_tokens).position(|(e, a)| {
+        let tolerance = tolerance.filter(|_| is_float_token(e));
+        let numeric = tolerance.and_then(|tol| match (e.parse::<f64>(), a.parse::<f64>()) {
+            (Ok(e), Ok(a)) if e.is_finite() && a.is_finite() => {
+                let diff = (e - a).abs();
+                Some(diff <= tol || diff <= tol * e.abs())
+            }
+            _ => None,
+        });
+        !numeric.unwrap_or(e == a)
+    });
+    match mismatch {
+        Some(i) => Some(i),
+        None if expected_tokens.len() != actual_tokens.len() => {
+            Some(expected_tokens.len().min(actual_tokens.len()))
+        }
+        None => None,
+    }
+}
+
+/// 1-based number of the first line that differs, or where one side runs out of lines
+fn first_line_mismatch(expected: &str, actual: &str) -> Option<usize> {
+    let (expected_lines, actual_lines) = (expected.lines().count(), actual.lines().count());
+    expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .or((expected_lines != actual_lines).then(|| expected_lines.min(actual_lines)))
+        .map(|i| i + 1)
+}
+
+/// Apply the normalization options to one output
+pub fn normalize(output: &str, opts: &NormalizationOptions) -> String {
+    let mut s = output.to_string();
+    if opts.normalize_crlf { s = s.replace("\r\n", "\n"); }
+    if opts.ignore_trailing_newline { s = strip_trailing_newline(&s).to_string(); }
+    if opts.ignore_extra_whitespace {
+        s = s
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    let s = s.lines().map(|l| l.trim()).collect::<Vec<_>>().join("\n").trim().to_string();
+    if opts.case_insensitive { s.to_lowercase() } else { s }
+}
+
+/// `text` with its lines in sorted order
+fn sort_lines(text: &str) -> String {
+    let mut lines: Vec<&str> = text.lines().collect();
+    lines.sort_unstable();
+    lines.join("\n")
+}
+
+/// Remove exactly one trailing `\n` (or `\r\n`), if present
+fn strip_trailing_newline(s: &str) -> &str {
+    s.strip_suffix('\n').map(|s| s.strip_suffix('\r').unwrap_or(s)).unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Verdict of `expected` vs `actual` with `unordered_lines` on
+    async fn unordered(expected: &str, actual: &str) -> TestVerdict {
+        let options = NormalizationOptions { unordered_lines: true, ..Default::default() };
+        let comparator = NormalizedComparator::new(options, 10);
+        comparator.compare(&ProgramInput::Text(""), expected, actual).await.verdict
+    }
+
+    #[tokio::test]
+    async fn unordered_lines_compare_as_multisets() {
+        assert_eq!(unordered("a\nb\nc\n", "c\na\nb\n").await, TestVerdict::Accepted);
+        assert_eq!(unordered("a\na\nb\n", "a\nb\nb\n").await, TestVerdict::WrongAnswer);
+        assert_eq!(unordered("a\na\nb\n", "a\nb\n").await, TestVerdict::WrongAnswer);
+    }
+}
@@ -1,63 +1,950 @@
+use crate::cache::{entry_stem, remove_old_files, CachePin, CompileCache, GcReport};
+#[cfg(unix)]
+use crate::executor::{limit_address_space, limit_cpu_time};
+use crate::types::{relative_source_path, CodeFile, Language};
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::process::Command as TokioCommand;
 use tempfile::TempDir;
 use tokio::fs;
 use tokio::time::{timeout, Duration};
 
-/// Handles compilation of C/C++ code
+/// A runnable build product
+#[derive(Debug, Clone)]
+pub enum Artifact {
+    /// Native executable produced by a compiler
+    Native { path: String },
+    /// Script (or bytecode) run through an interpreter; `argv[0]` is the interpreter
+    Interpreted { path: String, argv: Vec<String> },
+}
+
+impl Artifact {
+    /// Path of the file produced by compilation
+    pub fn path(&self) -> &str {
+        match self {
+            Artifact::Native { path } => path,
+            Artifact::Interpreted { path, .. } => path,
+        }
+    }
+
+    /// Directory the program reads at run time besides its executable (the script or
+    /// class files), which the sandbox has to expose
+    pub fn run_files(&self) -> Option<&Path> {
+        match self {
+            Artifact::Native { .. } => None,
+            Artifact::Interpreted { path, .. } => Path::new(path).parent(),
+        }
+    }
+
+    /// Build the command that runs this artifact
+    pub fn command(&self) -> TokioCommand {
+        match self {
+            Artifact::Native { path } => TokioCommand::new(path),
+            Artifact::Interpreted { argv, .. } => {
+                let mut cmd = TokioCommand::new(&argv[0]);
+                cmd.args(&argv[1..]);
+                cmd
+            }
+        }
+    }
+}
+
+/// Per-request compiler settings for C/C++
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompileOptions {
+    /// Extra flags appended after the defaults; each must pass the allowlist
+    #[serde(default)]
+    pub compile_flags: Vec<String>,
+    /// Replaces the default `-O2`, e.g. `"3"`, `"s"` or `"-O0"`
+    #[serde(default)]
+    pub opt_level: Option<String>,
+    /// Runtime checkers compiled into the binary
+    #[serde(default)]
+    pub sanitizers: Vec<Sanitizer>,
+    /// Compiler family used for C/C++ (default GCC)
+    #[serde(default)]
+    pub compiler_toolchain: Toolchain,
+    /// Language standard for C++ builds (default C++17)
+    #[serde(default)]
+    pub cpp_standard: CppStandard,
+    /// Language standard for C builds (default C99)
+    #[serde(default)]
+    pub c_standard: CStandard,
+    /// Link with `-static` so cached binaries run on hosts with a different libc.
+    /// Ignored with sanitizers, or when the toolchain has no static libraries
+    #[serde(default)]
+    pub static_link: bool,
+    /// Address-space cap for the compiler and its subprocesses (default 2048 MB)
+    #[serde(default)]
+    pub compile_memory_limit_mb: Option<u64>,
+    /// CPU-time cap for each compiler subprocess, in seconds; defaults to the compile
+    /// timeout, which alone only kills the driver and not a runaway `cc1plus`
+    #[serde(default)]
+    pub compile_cpu_limit_secs: Option<u64>,
+    /// Build with `-g -O0` for a debugger; the binary is copied to `debug_binary_dir`,
+    /// outside the cache, where `Judge::gc` removes it once unused for a while
+    #[serde(default)]
+    pub debug_build: bool,
+    /// Keep `#pragma GCC optimize`/`target` (and the clang spelling) in the source;
+    /// by default they are blanked out so every submission builds with the same flags
+    #[serde(default)]
+    pub allow_optimization_pragmas: bool,
+    /// Name given to the source file so diagnostics match the student's upload, e.g.
+    /// `main.cpp`; must be a bare name with a C/C++ extension (default `solution.c`/`.cpp`)
+    #[serde(default)]
+    pub source_filename: Option<String>,
+}
+
+/// Extensions accepted for `JudgeRequest::extra_headers`
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx", "inc"];
+
+/// Compiler address-space cap unless the request sets `compile_memory_limit_mb`
+const DEFAULT_COMPILE_MEMORY_LIMIT_MB: u64 = 2048;
+
+/// What `Compiler::compile_cached` builds: the main source and the headers it may include
+struct Sources<'a> {
+    name: &'a str,
+    code: &'a str,
+    headers: &'a [CodeFile],
+}
+
+/// Caps applied to a C/C++ compiler process
+#[derive(Debug, Clone, Copy)]
+struct ResourceLimits {
+    memory_mb: u64,
+    cpu_secs: u64,
+}
+
+/// C language standard selected with `-std=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CStandard {
+    #[default]
+    C99,
+    C11,
+    C17,
+}
+
+impl CStandard {
+    /// `-std=` spellings, preferred first
+    fn flags(self) -> &'static [&'static str] {
+        match self {
+            CStandard::C99 => &["-std=c99"],
+            CStandard::C11 => &["-std=c11"],
+            CStandard::C17 => &["-std=c17", "-std=c18"],
+        }
+    }
+}
+
+/// C++ language standard selected with `-std=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CppStandard {
+    Cpp11,
+    Cpp14,
+    #[default]
+    Cpp17,
+    Cpp20,
+    Cpp23,
+}
+
+impl CppStandard {
+    /// `-std=` spellings, preferred first; older compilers only know the draft names
+    fn flags(self) -> &'static [&'static str] {
+        match self {
+            CppStandard::Cpp11 => &["-std=c++11"],
+            CppStandard::Cpp14 => &["-std=c++14"],
+            CppStandard::Cpp17 => &["-std=c++17"],
+            CppStandard::Cpp20 => &["-std=c++20", "-std=c++2a"],
+            CppStandard::Cpp23 => &["-std=c++23", "-std=c++2b"],
+        }
+    }
+}
+
+/// C/C++ compiler family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Toolchain {
+    #[default]
+    Gcc,
+    Clang,
+}
+
+/// Sanitizer instrumentation for C/C++ builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+}
+
+impl CompileOptions {
+    /// Flags passed after the source file: optimization level, warnings, then user flags
+    fn flags(&self) -> Result<Vec<String>> {
+        let opt_flag = match self.opt_level.as_deref() {
+            _ if self.debug_build => "-O0".to_string(),
+            None => "-O2".to_string(),
+            Some(level) => {
+                let level = level.trim_start_matches("-O");
+                if !matches!(level, "0" | "1" | "2" | "3" | "s" | "g" | "fast") {
+                    return Err(anyhow::anyhow!("Invalid optimization level: {}", level));
+                }
+                format!("-O{}", level)
+            }
+        };
+        let mut flags = vec![opt_flag, "-Wall".to_string(), "-Wextra".to_string()];
+        if self.debug_build || self.sanitizers.contains(&Sanitizer::Address) {
+            flags.push("-g".to_string());
+        }
+        if self.sanitizers.contains(&Sanitizer::Address) {
+            flags.extend(["-fsanitize=address", "-fno-omit-frame-pointer"].map(String::from));
+        }
+        if self.sanitizers.contains(&Sanitizer::Undefined) {
+            flags.push("-fsanitize=undefined".to_string());
+        }
+        for flag in &self.compile_flags {
+            if !is_allowed_flag(flag) {
+                return Err(anyhow::anyhow!("Compiler flag not allowed: {}", flag));
+            }
+            flags.push(flag.clone());
+        }
+        Ok(flags)
+    }
+
+    /// `headers`, checked to be header files inside the build directory
+    fn check_headers<'a>(headers: &'a [CodeFile], source_name: &str) -> Result<&'a [CodeFile]> {
+        for header in headers {
+            let path = relative_source_path(&header.filename)?;
+            let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+            if !HEADER_EXTENSIONS.contains(&extension) || header.filename == source_name {
+                return Err(anyhow::anyhow!(
+                    "Invalid header name: {} (expected .{})",
+                    header.filename,
+                    HEADER_EXTENSIONS.join(", .")
+                ));
+            }
+        }
+        Ok(headers)
+    }
+
+    /// Reject a `source_filename` or `headers` that a single-file `language` build
+    /// would refuse, so the request fails before compiling; other languages ignore both
+    pub(crate) fn check_sources(&self, language: Language, headers: &[CodeFile]) -> Result<()> {
+        let language = match language {
+            Language::C => NativeLanguage::C,
+            Language::Cpp => NativeLanguage::Cpp,
+            _ => return Ok(()),
+        };
+        Self::check_headers(headers, self.source_name(language)?)?;
+        Ok(())
+    }
+
+    /// File name for the source: `source_filename` when set, otherwise the language default
+    fn source_name(&self, language: NativeLanguage) -> Result<&str> {
+        let Some(name) = self.source_filename.as_deref() else {
+            return Ok(language.source_name());
+        };
+        let bare = !name.starts_with('.') && !name.chars().any(|c| matches!(c, '/' | '\\') || c.is_control());
+        let extension = Path::new(name).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        if !bare || !language.extensions().contains(&extension) {
+            return Err(anyhow::anyhow!(
+                "Invalid source filename: {} (expected a file name ending in .{})",
+                name,
+                language.extensions().join(" or .")
+            ));
+        }
+        Ok(name)
+    }
+}
+
+impl CompileOptions {
+    /// Environment the instrumented binary should run with
+    pub fn runtime_env(&self) -> Vec<(String, String)> {
+        let mut env = Vec::new();
+        if self.sanitizers.contains(&Sanitizer::Address) {
+            // Leak checking needs ptrace and reports after main returns; keep runs deterministic
+            env.push(("ASAN_OPTIONS".to_string(), "detect_leaks=0:abort_on_error=0".to_string()));
+        }
+        if self.sanitizers.contains(&Sanitizer::Undefined) {
+            env.push(("UBSAN_OPTIONS".to_string(), "print_stacktrace=1".to_string()));
+        }
+        env
+    }
+
+    /// C/C++ `code` as it is compiled: optimization pragmas are blanked out unless
+    /// `allow_optimization_pragmas` is set
+    fn effective_source<'a>(&self, code: &'a str) -> std::borrow::Cow<'a, str> {
+        if self.allow_optimization_pragmas { code.into() } else { strip_optimization_pragmas(code) }
+    }
+}
+
+/// Allowlist for user-supplied flags: warnings, macros, codegen toggles and
+/// libraries, but nothing that names a file or forwards options to other tools
+fn is_allowed_flag(flag: &str) -> bool {
+    let simple = |s: &str| {
+        !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '=' | '+' | '.'))
+    };
+    if !flag.starts_with('-') || flag.contains("..") {
+        return false;
+    }
+    match flag {
+        "-w" | "-g" | "-pedantic" | "-pedantic-errors" | "-pthread" => true,
+        f if f.starts_with("-Wl,") || f.starts_with("-Wa,") || f.starts_with("-Wp,") => false,
+        f if f.starts_with("-W") || f.starts_with("-m") => simple(&f[2..]),
+        f if f.starts_with("-D") || f.starts_with("-U") => {
+            f[2..].chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '='))
+                && f.len() > 2
+        }
+        f if f.starts_with("-l") => {
+            f.len() > 2 && f[2..].chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        f if f.starts_with("-f") => {
+            const BLOCKED: &[&str] = &["-fplugin", "-fprofile", "-fdump", "-fauto-profile"];
+            !f.contains('=') && simple(&f[2..]) && !BLOCKED.iter().any(|b| f.starts_with(b))
+        }
+        _ => false,
+    }
+}
+
+/// Native toolchain front-ends sharing `Compiler::compile_native`
+#[derive(Debug, Clone, Copy)]
+enum NativeLanguage {
+    C,
+    Cpp,
+}
+
+impl NativeLanguage {
+    fn compiler(self, toolchain: Toolchain) -> &'static str {
+        match (self, toolchain) {
+            (NativeLanguage::C, Toolchain::Gcc) => "gcc",
+            (NativeLanguage::Cpp, Toolchain::Gcc) => "g++",
+            (NativeLanguage::C, Toolchain::Clang) => "clang",
+            (NativeLanguage::Cpp, Toolchain::Clang) => "clang++",
+        }
+    }
+
+    fn source_name(self) -> &'static str {
+        match self {
+            NativeLanguage::C => "solution.c",
+            NativeLanguage::Cpp => "solution.cpp",
+        }
+    }
+
+    /// File extensions the compiler recognises as this language
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            NativeLanguage::C => &["c"],
+            NativeLanguage::Cpp => &["cpp", "cc", "cxx", "c++"],
+        }
+    }
+
+    /// Candidate `-std=` flags for the requested standard, preferred first
+    fn std_flags(self, options: &CompileOptions) -> &'static [&'static str] {
+        match self {
+            NativeLanguage::C => options.c_standard.flags(),
+            NativeLanguage::Cpp => options.cpp_standard.flags(),
+        }
+    }
+
+    /// Value for `-x`, so a probe can compile from stdin
+    fn source_kind(self) -> &'static str {
+        match self {
+            NativeLanguage::C => "c",
+            NativeLanguage::Cpp => "c++",
+        }
+    }
+
+    /// Libraries linked after the sources; libm is harmless when unused and
+    /// spares C students the classic `undefined reference to sqrt`
+    fn link_flags(self) -> &'static [&'static str] {
+        match self {
+            NativeLanguage::C => &["-lm"],
+            NativeLanguage::Cpp => &[],
+        }
+    }
+
+    fn cache_suffix(self) -> &'static str {
+        match self {
+            NativeLanguage::C => "c",
+            NativeLanguage::Cpp => "cpp",
+        }
+    }
+}
+
+/// First line of `<compiler> --version`, identifying the exact toolchain build. Asked once
+/// per compiler; a compiler upgraded in place is noticed after a restart
+async fn compiler_version(compiler: &str) -> Result<String> {
+    static VERSIONS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    let versions = VERSIONS.get_or_init(Mutex::default);
+    if let Some(version) = versions.lock().unwrap().get(compiler) {
+        return Ok(version.clone());
+    }
+    let output = timeout(Duration::from_secs(5), TokioCommand::new(compiler).arg("--version").output())
+        .await
+        .with_context(|| format!("{} --version timeout", compiler))?
+        .map_err(|e| spawn_failure(compiler, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.lines().next().unwrap_or_default().trim().to_string();
+    versions.lock().unwrap().insert(compiler.to_string(), version.clone());
+    Ok(version)
+}
+
+/// Name and version of `compiler` for error reports, e.g. `g++ (GCC) 13.2.0`
+pub(crate) async fn toolchain_label(compiler: &str) -> String {
+    match compiler_version(compiler).await {
+        Ok(version) if version.contains(compiler) => version,
+        Ok(version) if !version.is_empty() => format!("{} {}", compiler, version),
+        _ => compiler.to_string(),
+    }
+}
+
+/// Whether `compiler` accepts `std_flag`, probed once per pair by compiling an empty file
+async fn supports_std_flag(compiler: &'static str, language: NativeLanguage, std_flag: &'static str) -> bool {
+    static PROBED: OnceLock<Mutex<HashMap<(&'static str, &'static str), bool>>> = OnceLock::new();
+    let probed = PROBED.get_or_init(Mutex::default);
+    if let Some(&supported) = probed.lock().unwrap().get(&(compiler, std_flag)) {
+        return supported;
+    }
+    let mut cmd = TokioCommand::new(compiler);
+    cmd.args([std_flag, "-fsyntax-only", "-x", language.source_kind(), "-"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true);
+    let supported = matches!(timeout(Duration::from_secs(5), cmd.status()).await, Ok(Ok(status)) if status.success());
+    probed.lock().unwrap().insert((compiler, std_flag), supported);
+    supported
+}
+
+/// Whether `compiler` can link a trivial program with `-static`, i.e. the toolchain
+/// ships static libraries; probed once per compiler
+async fn supports_static_linking(compiler: &'static str, language: NativeLanguage) -> bool {
+    static PROBED: OnceLock<Mutex<HashMap<&'static str, bool>>> = OnceLock::new();
+    let probed = PROBED.get_or_init(Mutex::default);
+    if let Some(&supported) = probed.lock().unwrap().get(compiler) {
+        return supported;
+    }
+    let supported = async {
+        let dir = TempDir::new().ok()?;
+        let source = dir.path().join(language.source_name());
+        fs::write(&source, "int main(void) { return 0; }\n").await.ok()?;
+        let mut cmd = TokioCommand::new(compiler);
+        cmd.arg("-static")
+            .arg(&source)
+            .arg("-o")
+            .arg(dir.path().join("probe"))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true);
+        Some(timeout(Duration::from_secs(10), cmd.status()).await.ok()?.ok()?.success())
+    }
+    .await
+    .unwrap_or(false);
+    probed.lock().unwrap().insert(compiler, supported);
+    supported
+}
+
+/// First `-std=` spelling of the requested standard that `compiler` understands
+async fn select_std_flag(compiler: &'static str, language: NativeLanguage, options: &CompileOptions) -> Result<&'static str> {
+    let candidates = language.std_flags(options);
+    for &flag in candidates {
+        if supports_std_flag(compiler, language, flag).await {
+            return Ok(flag);
+        }
+    }
+    Err(UnsupportedStandard { compiler: compiler.to_string(), standard: candidates[0] }.into())
+}
+
+/// Successful compilation: the runnable artifact plus any compiler warnings
+#[derive(Debug, Clone)]
+pub struct Build {
+    pub artifact: Artifact,
+    pub warnings: Option<String>,
+    /// The compiler command line (for cache hits, the one that produced the cached binary)
+    pub command: String,
+    /// Served from the compile cache without running the compiler
+    pub cached: bool,
+    /// On cache hits, how long the compiler ran when the entry was built (if recorded)
+    pub cached_build_time_ms: Option<u64>,
+}
+
+/// Blank out `#pragma GCC optimize(...)`/`#pragma GCC target(...)` lines (and
+/// `#pragma clang optimize`), keeping line numbers for diagnostics
+fn strip_optimization_pragmas(code: &str) -> std::borrow::Cow<'_, str> {
+    static PRAGMA: OnceLock<regex::Regex> = OnceLock::new();
+    let pragma = PRAGMA.get_or_init(|| {
+        regex::Regex::new(r"(?m)^[ \t]*#[ \t]*pragma[ \t]+(?:GCC|clang)[ \t]+(?:optimize|target)\b.*$").expect("valid pragma pattern")
+    });
+    pragma.replace_all(code, "")
+}
+
+/// Where `debug_build` binaries are kept until `Judge::gc` finds them unused
+pub fn debug_binary_dir() -> PathBuf {
+    std::env::temp_dir().join("dsa_judge_debug")
+}
+
+/// Remove `debug-*` binaries in `debug_binary_dir` unused for longer than `max_age`
+pub(crate) fn clean_old_debug_binaries(max_age: Duration) -> GcReport {
+    remove_old_files(&debug_binary_dir(), "debug-", max_age)
+}
+
+/// Copy a debug build out of the cache into `dir`. Builds of one cache entry share a
+/// copy, named by its hash, whose age is reset on every reuse
+fn keep_debug_binary(dir: &Path, path: &str) -> Result<PathBuf> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    std::fs::create_dir_all(dir).context("Failed to create debug binary directory")?;
+    let nonce = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let key = match entry_stem(Path::new(path)) {
+        Some(stem) => stem.to_string(),
+        // Built outside the cache (it was unwritable), so there is nothing to share
+        None => {
+            let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros()).unwrap_or(0);
+            format!("{}-{}", ts, nonce)
+        }
+    };
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    let dest = dir.join(format!("debug-{}{}", key, suffix));
+    if let Ok(file) = std::fs::File::open(&dest) {
+        let _ = file.set_modified(SystemTime::now());
+        return Ok(dest);
+    }
+    // Renamed into place so a concurrent identical build never hands out a partial copy
+    let partial = dir.join(format!("debug-{}.partial-{}-{}", key, std::process::id(), nonce));
+    let copied = std::fs::copy(path, &partial).and_then(|_| std::fs::rename(&partial, &dest));
+    if copied.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    copied.context("Failed to copy debug binary")?;
+    Ok(dest)
+}
+
+/// Compiler stderr from a successful build, if it said anything
+fn warnings_from(stderr: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(stderr).trim_end().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+/// Name of the Python 3 interpreter on this platform
+fn python_interpreter() -> &'static str {
+    if cfg!(windows) { "python" } else { "python3" }
+}
+
+/// Java source with comments and string/char literals blanked out, so searches for
+/// declarations only see code
+fn java_code_only(code: &str) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                if chars.by_ref().any(|c| c == '\n') {
+                    out.push('\n');
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            // A text block's content ends up between the pairs of its `"""` delimiters
+            '"' | '\'' => {
+                let mut escaped = false;
+                for d in chars.by_ref() {
+                    match d {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        _ if d == c => break,
+                        _ => {}
+                    }
+                }
+                out.push_str("\"\"");
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether comment-free Java source declares a `static void main(`, modifiers in any order
+fn java_declares_main(code: &str) -> bool {
+    static MAIN: OnceLock<regex::Regex> = OnceLock::new();
+    let main = MAIN.get_or_init(|| {
+        regex::Regex::new(r"\b((?:(?:public|protected|private|static|final|synchronized|strictfp)\s+)+)void\s+main\s*\(")
+            .expect("valid main pattern")
+    });
+    main.captures_iter(code).any(|m| m[1].split_whitespace().any(|modifier| modifier == "static"))
+}
+
+/// Find the name of the top-level public class in Java source, if any
+fn java_public_class(code: &str) -> Option<String> {
+    let tokens: Vec<&str> = code.split_whitespace().collect();
+    for (i, tok) in tokens.iter().enumerate() {
+        if *tok != "public" {
+            continue;
+        }
+        // Skip modifiers between `public` and `class`
+        let mut j = i + 1;
+        while j < tokens.len() && matches!(tokens[j], "final" | "abstract" | "strictfp") {
+            j += 1;
+        }
+        if tokens.get(j) == Some(&"class") {
+            let name: String = tokens.get(j + 1)?
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+                .collect();
+            if !name.is_empty() {
+                return Some(name);
+            }
+        }
+    }
+    None
+}
+
+/// Compiler default wall-clock limit per invocation
+pub const DEFAULT_COMPILE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest accepted source file unless overridden (256 KB)
+pub const DEFAULT_MAX_SOURCE_BYTES: usize = 256 * 1024;
+
+/// A compiler ran past its time limit (as opposed to rejecting the code)
+#[derive(Debug, thiserror::Error)]
+#[error("{compiler} timed out after {} ms", limit.as_millis())]
+pub struct CompileTimeout {
+    pub compiler: String,
+    pub limit: Duration,
+}
+
+/// A compiler or interpreter isn't installed, as opposed to rejecting the code
+#[derive(Debug, thiserror::Error)]
+#[error("{compiler} not found; install it or add it to PATH")]
+pub struct CompilerNotFound {
+    pub compiler: String,
+}
+
+/// The installed compiler is too old for the requested `-std=`; an environment
+/// problem rather than a fault in the code
+#[derive(Debug, thiserror::Error)]
+#[error("{compiler} does not support {standard}")]
+pub struct UnsupportedStandard {
+    pub compiler: String,
+    pub standard: &'static str,
+}
+
+/// Wrap a failure to start `compiler`, singling out a missing executable
+pub(crate) fn spawn_failure(compiler: &str, err: std::io::Error) -> anyhow::Error {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        CompilerNotFound { compiler: compiler.to_string() }.into()
+    } else {
+        anyhow::Error::new(err).context(format!("Failed to execute {}", compiler))
+    }
+}
+
+/// A compiler hit its memory or CPU-time cap, e.g. on a template or constexpr bomb
+#[derive(Debug, thiserror::Error)]
+#[error("{compiler} exceeded its {resource} limit")]
+pub struct CompileResourceLimit {
+    pub compiler: String,
+    /// `"memory"` or `"CPU time"`
+    pub resource: &'static str,
+}
+
+/// Which cap a failed compiler run hit, judging by how it died: the signal when the
+/// compiler itself was killed, else the driver's own fatal line for a killed subprocess
+/// (`cc1plus: out of memory ...`). Only whole lines starting with a tool name count, so
+/// diagnostics quoting the submission (`std::bad_alloc` in a source line) never match.
+fn exceeded_resource(status: &std::process::ExitStatus, stderr: &[u8]) -> Option<&'static str> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        match status.signal() {
+            Some(libc::SIGXCPU) => return Some("CPU time"),
+            // The address-space cap makes allocations fail, so a SIGKILL is the OOM killer
+            Some(libc::SIGKILL) => return Some("memory"),
+            _ => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = status;
+    static FATAL: OnceLock<regex::Regex> = OnceLock::new();
+    let fatal = FATAL.get_or_init(|| {
+        regex::Regex::new(
+            r"(?m)^(?:[\w.+-]+: )?(?:(?:fatal error|internal compiler error): (?P<signal>Killed|CPU time limit exceeded) signal terminated program|out of memory allocating|virtual memory exhausted|LLVM ERROR: out of memory|memory allocation of \d+ bytes failed)",
+        )
+        .expect("valid compiler fatal-error pattern")
+    });
+    let stderr = String::from_utf8_lossy(stderr);
+    let found = fatal.captures(&stderr)?;
+    match found.name("signal").map(|signal| signal.as_str()) {
+        Some("CPU time limit exceeded") => Some("CPU time"),
+        _ => Some("memory"),
+    }
+}
+
+/// The compiler rejected the code
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct CompileFailure {
+    pub message: String,
+    /// Raw diagnostics, when `message` is a friendlier rewrite of them
+    pub output: Option<String>,
+    /// The compiler command line that was run
+    pub command: String,
+    /// Everything the compiler printed to stderr
+    pub stderr: String,
+    /// Name and version of the compiler that rejected the code
+    pub toolchain: String,
+}
+
+/// Linker diagnostics (GNU ld, lld, ld64) for a program without `main`
+fn is_missing_main(stderr: &str) -> bool {
+    ["undefined reference to `main'", "undefined symbol: main", "\"_main\", referenced from"]
+        .iter()
+        .any(|marker| stderr.contains(marker))
+}
+
+/// Turn a failed build's stderr into a `CompileFailure`, explaining common beginner mistakes
+async fn compile_failure(compiler: &str, command: String, stderr: &[u8]) -> anyhow::Error {
+    let error = String::from_utf8_lossy(stderr).to_string();
+    let toolchain = toolchain_label(compiler).await;
+    let failure = if is_missing_main(&error) {
+        CompileFailure {
+            message: "Your program is missing a main() function.".to_string(),
+            output: Some(error.clone()),
+            command,
+            stderr: error,
+            toolchain,
+        }
+    } else {
+        CompileFailure {
+            message: format!("Compilation failed ({}): {}", toolchain, error),
+            output: None,
+            command,
+            stderr: error,
+            toolchain,
+        }
+    };
+    failure.into()
+}
+
+/// Shell-style rendering of a command's argv, for reproducing a build by hand
+pub(crate) fn command_line(cmd: &TokioCommand) -> String {
+    let cmd = cmd.as_std();
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "'\"\\$`".contains(c)) {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Handles compilation of submitted code
 pub struct Compiler {
     temp_dir: TempDir,
+    compile_timeout: Duration,
+    max_source_bytes: usize,
+    cache: CompileCache,
 }
 
 impl Compiler {
     pub fn new() -> Result<Self> {
         let temp_dir = TempDir::new().context("Failed to create temporary directory")?;
-        Ok(Self { temp_dir })
+        Ok(Self {
+            temp_dir,
+            compile_timeout: DEFAULT_COMPILE_TIMEOUT,
+            max_source_bytes: DEFAULT_MAX_SOURCE_BYTES,
+            cache: CompileCache::default(),
+        })
+    }
+
+    /// Use a specific on-disk cache for compiled binaries
+    pub fn with_cache(mut self, cache: CompileCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Keep `artifact`'s cache entry from being evicted while the guard is held
+    pub fn pin(&self, artifact: &Artifact) -> CachePin {
+        self.cache.pin(Path::new(artifact.path()))
+    }
+
+    /// Override the largest source accepted by the compile functions
+    pub fn with_max_source_bytes(mut self, max_source_bytes: usize) -> Self {
+        self.max_source_bytes = max_source_bytes;
+        self
+    }
+
+    fn check_source_size(&self, code: &str) -> Result<()> {
+        if code.len() > self.max_source_bytes {
+            return Err(anyhow::anyhow!(
+                "Source too large: {} bytes (limit {} bytes)",
+                code.len(),
+                self.max_source_bytes
+            ));
+        }
+        Ok(())
+    }
+
+    /// Override the wall-clock limit applied to each compiler invocation
+    pub fn with_compile_timeout(mut self, compile_timeout: Duration) -> Self {
+        self.compile_timeout = compile_timeout;
+        self
+    }
+
+    /// Compile C code, with `headers` beside it, and return the executable artifact
+    /// (with on-disk cache)
+    pub async fn compile_c(&self, code: &str, options: &CompileOptions, headers: &[CodeFile]) -> Result<Build> {
+        self.compile_native(code, NativeLanguage::C, options, headers).await
+    }
+
+    /// Compile C++ code, with `headers` beside it, and return the executable artifact
+    /// (with on-disk cache)
+    pub async fn compile_cpp(&self, code: &str, options: &CompileOptions, headers: &[CodeFile]) -> Result<Build> {
+        self.compile_native(code, NativeLanguage::Cpp, options, headers).await
     }
 
-    /// Compile C code and return the executable path (with on-disk cache)
-    pub async fn compile_c(&self, code: &str) -> Result<String> {
-        let source_path = self.temp_dir.path().join("solution.c");
+    /// Compile a single-file Rust program with `rustc -O` (with on-disk cache)
+    pub async fn compile_rust(&self, code: &str) -> Result<Build> {
+        let args = vec!["-O".to_string(), "--edition=2021".to_string()];
+        self.compile_cached(Sources { name: "main.rs", code, headers: &[] }, "rustc", &args, "rs", None).await
+    }
+
+    async fn compile_native(&self, code: &str, language: NativeLanguage, options: &CompileOptions, headers: &[CodeFile]) -> Result<Build> {
+        // Everything except the (per-run) file paths
+        let compiler = language.compiler(options.compiler_toolchain);
+        let std_flag = select_std_flag(compiler, language, options).await?;
+        let mut args = vec!["-pipe".to_string(), std_flag.to_string()];
+        args.extend(options.flags()?);
+        args.extend(language.link_flags().iter().map(|f| f.to_string()));
+        if options.static_link && options.sanitizers.is_empty() && supports_static_linking(compiler, language).await {
+            args.push("-static".to_string());
+        }
+        let limits = ResourceLimits {
+            memory_mb: options.compile_memory_limit_mb.unwrap_or(DEFAULT_COMPILE_MEMORY_LIMIT_MB),
+            cpu_secs: options.compile_cpu_limit_secs.unwrap_or(self.compile_timeout.as_secs_f64().ceil() as u64),
+        };
+        let code = options.effective_source(code);
+        let source_name = options.source_name(language)?;
+        let headers = CompileOptions::check_headers(headers, source_name)?;
+        let mut build = self
+            .compile_cached(Sources { name: source_name, code: &code, headers }, compiler, &args, language.cache_suffix(), Some(limits))
+            .await?;
+        if options.debug_build {
+            let path = keep_debug_binary(&debug_binary_dir(), build.artifact.path())?;
+            build.artifact = Artifact::Native { path: path.to_string_lossy().to_string() };
+        }
+        Ok(build)
+    }
+
+    /// Run `compiler -o <exe> <source> args...`, keyed in the cache by source, headers,
+    /// compiler and args
+    async fn compile_cached(
+        &self,
+        sources: Sources<'_>,
+        compiler: &str,
+        args: &[String],
+        cache_suffix: &str,
+        limits: Option<ResourceLimits>,
+    ) -> Result<Build> {
+        let Sources { name: source_name, code, headers } = sources;
+        let source_path = self.temp_dir.path().join(source_name);
         let executable_path = self.temp_dir.path().join("solution.exe");
 
-        // Simple cache by hash(code)
+        // Checked before the cache lookup, so a cached build can't slip past a smaller limit
+        self.check_source_size(code)?;
+        let header_paths = headers
+            .iter()
+            .map(|header| {
+                self.check_source_size(&header.content)?;
+                Ok(self.temp_dir.path().join(relative_source_path(&header.filename)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Cache by hash(code + compiler identity + file name + argv + headers) so flag changes or a
+        // compiler upgrade never serve a stale binary, and cached warnings name the right file
+        let version = compiler_version(compiler).await?;
         let mut hasher = sha1_smol::Sha1::new();
         hasher.update(code.as_bytes());
+        for part in [compiler, version.as_str(), source_name].into_iter().chain(args.iter().map(String::as_str)) {
+            hasher.update(b"\0");
+            hasher.update(part.as_bytes());
+        }
+        for header in headers {
+            for part in [&header.filename, &header.content] {
+                hasher.update(b"\0");
+                hasher.update(part.as_bytes());
+            }
+        }
         let hash = hasher.digest().to_string();
-        let cache_dir = dirs::cache_dir().unwrap_or(std::env::temp_dir()).join("dsa_judge_cache");
-        let cache_path = cache_dir.join(format!("{}_c.exe", hash));
-        if cache_path.exists() {
-            return Ok(cache_path.to_string_lossy().to_string());
+        let cache_name = format!("{}_{}.exe", hash, cache_suffix);
+
+        // Compile with the selected toolchain (async + timeout)
+        let mut cmd = TokioCommand::new(compiler);
+        cmd.arg("-o").arg(&executable_path)
+            .arg(&source_path)
+            .args(args)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        if let Some(limits) = limits {
+            limit_address_space(&mut cmd, limits.memory_mb * 1024 * 1024);
+            limit_cpu_time(&mut cmd, limits.cpu_secs);
+        }
+        let command = command_line(&cmd);
+        // Warnings and the build time are cached next to the binary so cache hits still report them
+        let warnings_path = self.cache.path(&cache_name).with_extension("warnings");
+        let build_time_path = self.cache.path(&cache_name).with_extension("build_ms");
+        if let Some(cache_path) = self.cache.lookup(&cache_name) {
+            let warnings = std::fs::read(&warnings_path).ok().and_then(|w| warnings_from(&w));
+            return Ok(Build {
+                artifact: Artifact::Native { path: cache_path.to_string_lossy().to_string() },
+                warnings,
+                command,
+                cached: true,
+                cached_build_time_ms: std::fs::read_to_string(&build_time_path).ok().and_then(|ms| ms.trim().parse().ok()),
+            });
         }
 
         // Write code to file
-        if code.as_bytes().len() > 256 * 1024 { // 256 KB
-            return Err(anyhow::anyhow!("Source too large"));
-        }
         fs::write(&source_path, code)
             .await
             .context("Failed to write source code")?;
+        for (header, header_path) in headers.iter().zip(header_paths) {
+            if let Some(parent) = header_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&header_path, &header.content)
+                .await
+                .with_context(|| format!("Failed to write header {}", header.filename))?;
+        }
 
-        // Compile with GCC (async + timeout)
-        let mut cmd = TokioCommand::new("gcc");
-        cmd.arg("-pipe")
-            .arg("-o").arg(&executable_path)
-            .arg(&source_path)
-            .arg("-std=c99")
-            .arg("-O2")
-            .arg("-Wall")
-            .arg("-Wextra")
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        let output = timeout(Duration::from_secs(10), cmd.output())
+        let build_start = std::time::Instant::now();
+        let output = timeout(self.compile_timeout, cmd.output())
             .await
-            .context("gcc timeout")?
-            .context("Failed to execute gcc")?;
+            .map_err(|_| CompileTimeout { compiler: compiler.to_string(), limit: self.compile_timeout })?
+            .map_err(|e| spawn_failure(compiler, e))?;
+        let build_time_ms = build_start.elapsed().as_millis() as u64;
 
         if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Compilation failed: {}", error));
+            if let Some(resource) = limits.and_then(|_| exceeded_resource(&output.status, &output.stderr)) {
+                return Err(CompileResourceLimit { compiler: compiler.to_string(), resource }.into());
+            }
+            return Err(compile_failure(compiler, command, &output.stderr).await);
         }
 
         if let Ok(meta) = std::fs::metadata(&executable_path) {
@@ -65,79 +952,220 @@ impl Compiler {
                 return Err(anyhow::anyhow!("Executable too large"));
             }
         }
-        // Move/copy to cache
-        std::fs::create_dir_all(&cache_dir).ok();
-        let _ = std::fs::copy(&executable_path, &cache_path);
-        Ok(cache_path.to_string_lossy().to_string())
+        // Move/copy to cache; fall back to the build dir if the cache is unwritable
+        let cache_path = match self.cache.insert(&cache_name, &executable_path) {
+            Ok(path) => {
+                let _ = std::fs::write(&warnings_path, &output.stderr);
+                let _ = std::fs::write(&build_time_path, build_time_ms.to_string());
+                path
+            }
+            Err(_) => executable_path,
+        };
+        Ok(Build {
+            artifact: Artifact::Native { path: cache_path.to_string_lossy().to_string() },
+            warnings: warnings_from(&output.stderr),
+            command,
+            cached: false,
+            cached_build_time_ms: None,
+        })
     }
 
-    /// Compile C++ code and return the executable path (with on-disk cache)
-    pub async fn compile_cpp(&self, code: &str) -> Result<String> {
-        let source_path = self.temp_dir.path().join("solution.cpp");
-        let executable_path = self.temp_dir.path().join("solution.exe");
+    /// Validate Python source with `py_compile` and return a script artifact
+    pub async fn compile_python(&self, code: &str) -> Result<Build> {
+        let source_path = self.temp_dir.path().join("solution.py");
 
-        let mut hasher = sha1_smol::Sha1::new();
-        hasher.update(code.as_bytes());
-        let hash = hasher.digest().to_string();
-        let cache_dir = dirs::cache_dir().unwrap_or(std::env::temp_dir()).join("dsa_judge_cache");
-        let cache_path = cache_dir.join(format!("{}_cpp.exe", hash));
-        if cache_path.exists() {
-            return Ok(cache_path.to_string_lossy().to_string());
+        self.check_source_size(code)?;
+        fs::write(&source_path, code)
+            .await
+            .context("Failed to write source code")?;
+
+        // Syntax check only; the script itself is run by the interpreter
+        let interpreter = python_interpreter();
+        let mut cmd = TokioCommand::new(interpreter);
+        cmd.arg("-m")
+            .arg("py_compile")
+            .arg(&source_path)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        let output = timeout(self.compile_timeout, cmd.output())
+            .await
+            .map_err(|_| CompileTimeout { compiler: "py_compile".to_string(), limit: self.compile_timeout })?
+            .map_err(|e| spawn_failure(interpreter, e))?;
+
+        if !output.status.success() {
+            return Err(compile_failure(interpreter, command_line(&cmd), &output.stderr).await);
         }
 
-        // Write code to file
-        if code.as_bytes().len() > 256 * 1024 {
-            return Err(anyhow::anyhow!("Source too large"));
+        let path = source_path.to_string_lossy().to_string();
+        Ok(Build {
+            artifact: Artifact::Interpreted {
+                argv: vec![interpreter.to_string(), path.clone()],
+                path,
+            },
+            warnings: warnings_from(&output.stderr),
+            command: command_line(&cmd),
+            cached: false,
+            cached_build_time_ms: None,
+        })
+    }
+
+    /// Compile Java code with javac and return an artifact that runs it on the JVM.
+    /// The heap is capped with `-Xmx` so the JVM honours the problem's memory limit.
+    pub async fn compile_java(&self, code: &str, memory_limit_mb: u64) -> Result<Build> {
+        self.check_source_size(code)?;
+
+        // javac requires the file name to match the public class
+        let declarations = java_code_only(code);
+        let class_name = java_public_class(&declarations).unwrap_or_else(|| "Main".to_string());
+        if !java_declares_main(&declarations) {
+            return Err(anyhow::anyhow!(
+                "Compilation failed: class {} must declare `public static void main(String[] args)`",
+                class_name
+            ));
         }
+        let source_path = self.temp_dir.path().join(format!("{}.java", class_name));
         fs::write(&source_path, code)
             .await
             .context("Failed to write source code")?;
 
-        // Compile with G++ (async + timeout)
-        let mut cmd = TokioCommand::new("g++");
-        cmd.arg("-pipe")
-            .arg("-o").arg(&executable_path)
+        let mut cmd = TokioCommand::new("javac");
+        cmd.arg("-encoding").arg("UTF-8")
+            .arg("-d").arg(self.temp_dir.path())
             .arg(&source_path)
-            .arg("-std=c++17")
-            .arg("-O2")
-            .arg("-Wall")
-            .arg("-Wextra")
             .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        let output = timeout(Duration::from_secs(10), cmd.output())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        let output = timeout(self.compile_timeout, cmd.output())
             .await
-            .context("g++ timeout")?
-            .context("Failed to execute g++")?;
+            .map_err(|_| CompileTimeout { compiler: "javac".to_string(), limit: self.compile_timeout })?
+            .map_err(|e| spawn_failure("javac", e))?;
 
         if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Compilation failed: {}", error));
+            return Err(compile_failure("javac", command_line(&cmd), &output.stderr).await);
         }
 
-        if let Ok(meta) = std::fs::metadata(&executable_path) {
-            if meta.len() > 64 * 1024 * 1024 {
-                return Err(anyhow::anyhow!("Executable too large"));
-            }
+        let class_dir = self.temp_dir.path().to_string_lossy().to_string();
+        let class_path = self.temp_dir.path().join(format!("{}.class", class_name));
+        Ok(Build {
+            artifact: Artifact::Interpreted {
+                path: class_path.to_string_lossy().to_string(),
+                argv: vec![
+                    "java".to_string(),
+                    format!("-Xmx{}m", memory_limit_mb.max(16)),
+                    "-Xss64m".to_string(),
+                    "-cp".to_string(),
+                    class_dir,
+                    class_name,
+                ],
+            },
+            warnings: warnings_from(&output.stderr),
+            command: command_line(&cmd),
+            cached: false,
+            cached_build_time_ms: None,
+        })
+    }
+
+    /// Check that the C and C++ compilers of a toolchain are available. Blocks on the
+    /// compilers the first time; a toolchain found once is not probed again, a missing
+    /// one is retried so installing it needs no restart
+    pub fn check_toolchain(toolchain: Toolchain) -> Result<()> {
+        static INSTALLED: Mutex<Vec<Toolchain>> = Mutex::new(Vec::new());
+        if INSTALLED.lock().unwrap().contains(&toolchain) {
+            return Ok(());
+        }
+        for language in [NativeLanguage::C, NativeLanguage::Cpp] {
+            let compiler = language.compiler(toolchain);
+            Command::new(compiler)
+                .arg("--version")
+                .output()
+                .with_context(|| format!("{} not found. Please install the {:?} toolchain", compiler, toolchain))?;
         }
-        std::fs::create_dir_all(&cache_dir).ok();
-        let _ = std::fs::copy(&executable_path, &cache_path);
-        Ok(cache_path.to_string_lossy().to_string())
+        INSTALLED.lock().unwrap().push(toolchain);
+        Ok(())
+    }
+
+    /// `check_toolchain` off the async runtime's worker threads
+    pub(crate) async fn ensure_toolchain(toolchain: Toolchain) -> Result<()> {
+        tokio::task::spawn_blocking(move || Self::check_toolchain(toolchain)).await?
     }
 
-    /// Check if required compilers are available
+    /// Check if required compilers are available (any supported toolchain will do)
     pub fn check_compilers() -> Result<()> {
-        // Check for GCC
-        Command::new("gcc")
-            .arg("--version")
-            .output()
-            .context("GCC not found. Please install GCC compiler")?;
-
-        // Check for G++
-        Command::new("g++")
-            .arg("--version")
-            .output()
-            .context("G++ not found. Please install G++ compiler")?;
+        Self::check_toolchain(Toolchain::Gcc).or_else(|gcc_err| {
+            Self::check_toolchain(Toolchain::Clang).map_err(|_| gcc_err)
+        })
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn java_main_is_found_in_code_only() {
+        let declares = |code: &str| java_declares_main(&java_code_only(code));
+        assert!(declares("public class A { public static void main(String[] a) {} }"));
+        assert!(declares("class A { static public  void\n main (String... a) {} }"));
+        assert!(declares("class A { final static void main(String[] a) {} }"));
+        assert!(!declares("class A { // static void main(\n }"));
+        assert!(!declares("class A { /* public static void main( */ }"));
+        assert!(!declares("class A { String s = \"static void main(\"; }"));
+        assert!(!declares("class A { public void main(String[] a) {} }"));
+        assert_eq!(java_public_class(&java_code_only("// public class Old\npublic class New {}")).as_deref(), Some("New"));
+    }
+
+    #[cfg(unix)]
+    fn exited(code: i32) -> std::process::ExitStatus {
+        std::os::unix::process::ExitStatusExt::from_raw(code << 8)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exceeded_resource_reads_fatal_lines_only() {
+        let echoed = b"main.cpp:3:5: error: 'bad_alloc' was not declared\n    3 |     throw std::bad_alloc(); // out of memory allocating\n";
+        assert_eq!(exceeded_resource(&exited(1), echoed), None);
+        let oom = b"cc1plus: out of memory allocating 65536 bytes after a total of 1048576 bytes\n";
+        assert_eq!(exceeded_resource(&exited(1), oom), Some("memory"));
+        let cpu = b"g++: fatal error: CPU time limit exceeded signal terminated program cc1plus\ncompilation terminated.\n";
+        assert_eq!(exceeded_resource(&exited(1), cpu), Some("CPU time"));
+        let killed = std::os::unix::process::ExitStatusExt::from_raw(libc::SIGXCPU);
+        assert_eq!(exceeded_resource(&killed, b""), Some("CPU time"));
+    }
+
+    #[test]
+    fn optimization_pragmas_are_stripped_unless_allowed() {
+        let code = "#pragma GCC optimize(\"O3\")\n  # pragma GCC target(\"avx2\")\n#pragma once\nint main() {}\n";
+        let disallowed = CompileOptions::default();
+        assert_eq!(disallowed.effective_source(code), "\n\n#pragma once\nint main() {}\n");
+        let allowed = CompileOptions { allow_optimization_pragmas: true, ..Default::default() };
+        assert_eq!(allowed.effective_source(code), code);
+    }
+
+    #[test]
+    fn debug_binaries_of_one_build_share_a_copy() {
+        let (cache, debug) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let cached = cache.path().join(format!("{:040x}_c.exe", 1));
+        std::fs::write(&cached, b"binary").unwrap();
+        let cached = cached.to_string_lossy();
+
+        let first = keep_debug_binary(debug.path(), &cached).unwrap();
+        assert_eq!(keep_debug_binary(debug.path(), &cached).unwrap(), first);
+        assert_eq!(std::fs::read_dir(debug.path()).unwrap().count(), 1);
+        assert_eq!(remove_old_files(debug.path(), "debug-", Duration::ZERO).removed_files, 1);
+    }
+
+    #[tokio::test]
+    async fn cached_builds_still_honour_the_source_limit() {
+        let cache = tempfile::tempdir().unwrap();
+        let cache = CompileCache::new(cache.path(), u64::MAX);
+        let code = "int main(void) { return 0; }\n";
+        let options = CompileOptions::default();
+        let build = Compiler::new().unwrap().with_cache(cache.clone()).compile_c(code, &options, &[]).await;
+        assert!(!build.unwrap().cached);
+
+        let limited = Compiler::new().unwrap().with_cache(cache).with_max_source_bytes(8);
+        let err = limited.compile_c(code, &options, &[]).await.unwrap_err();
+        assert!(err.to_string().contains("Source too large"), "{}", err);
     }
 }
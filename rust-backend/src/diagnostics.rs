@@ -0,0 +1,70 @@
+//! Structured compiler diagnostics parsed from GCC/Clang's default text format:
+//!
+//! ```text
+//! solution.cpp:4:12: error: expected ';' before '}' token
+//! ```
+//!
+//! Lines that don't follow it (source excerpts, "In function" headers, linker
+//! output) are skipped, so callers should keep the raw text as a fallback.
+
+use serde::{Deserialize, Serialize};
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// One `file:line:column: severity: message` line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    /// Missing when the compiler was told not to report columns
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Markers between the location and the message, checked in this order
+const SEVERITIES: &[(&str, Severity)] = &[
+    (": fatal error: ", Severity::Error),
+    (": error: ", Severity::Error),
+    (": warning: ", Severity::Warning),
+    (": note: ", Severity::Note),
+];
+
+/// Every diagnostic line in `text`, in order; empty if none could be parsed
+pub fn parse(text: &str) -> Vec<Diagnostic> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+/// `text` cut to its first `max_lines` lines plus a marker saying how many were
+/// dropped, and the original line count; `None` if it already fits
+pub fn truncate_lines(text: &str, max_lines: usize) -> Option<(String, usize)> {
+    let total = text.lines().count();
+    if total <= max_lines {
+        return None;
+    }
+    let mut kept: Vec<&str> = text.lines().take(max_lines).collect();
+    let marker = format!("...(truncated, {} more lines)", total - max_lines);
+    kept.push(&marker);
+    Some((kept.join("\n"), total))
+}
+
+fn parse_line(line: &str) -> Option<Diagnostic> {
+    let (location, severity, message) = SEVERITIES.iter().find_map(|&(marker, severity)| {
+        line.split_once(marker).map(|(location, message)| (location, severity, message))
+    })?;
+    // Split from the right: Windows paths contain a drive-letter colon
+    let (rest, last) = location.rsplit_once(':')?;
+    let last = last.parse().ok()?;
+    let (file, line, column) = match rest.rsplit_once(':').and_then(|(file, line)| Some((file, line.parse().ok()?))) {
+        Some((file, line)) => (file, line, Some(last)),
+        None => (rest, last, None),
+    };
+    Some(Diagnostic { file: file.to_string(), line, column, severity, message: message.to_string() })
+}
@@ -0,0 +1,123 @@
+//! Line-based unified diff used to explain wrong answers.
+
+/// Lines of context kept around each change
+const CONTEXT: usize = 3;
+
+/// Above this many cells the LCS table is skipped and the differing middle
+/// section is reported as a single replacement
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Edit script turning `a` into `b`, as (op, index into a, index into b)
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<(Op, usize, usize)> = (0..prefix).map(|i| (Op::Equal, i, i)).collect();
+
+    if a_mid.len() * b_mid.len() <= MAX_LCS_CELLS {
+        // lcs[i][j] = LCS length of a_mid[i..] and b_mid[j..]
+        let (n, m) = (a_mid.len(), b_mid.len());
+        let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if a_mid[i] == b_mid[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a_mid[i] == b_mid[j] {
+                ops.push((Op::Equal, prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                ops.push((Op::Delete, prefix + i, prefix + j));
+                i += 1;
+            } else {
+                ops.push((Op::Insert, prefix + i, prefix + j));
+                j += 1;
+            }
+        }
+    } else {
+        ops.extend((0..a_mid.len()).map(|i| (Op::Delete, prefix + i, prefix)));
+        ops.extend((0..b_mid.len()).map(|j| (Op::Insert, prefix + a_mid.len(), prefix + j)));
+    }
+
+    let (a_end, b_end) = (a.len() - suffix, b.len() - suffix);
+    ops.extend((0..suffix).map(|k| (Op::Equal, a_end + k, b_end + k)));
+    ops
+}
+
+/// Unified diff from `expected` to `actual`, capped at `max_lines` output lines.
+/// Returns `None` when the inputs are identical line by line.
+pub fn unified_diff(expected: &str, actual: &str, max_lines: usize) -> Option<String> {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+    let ops = edit_script(&a, &b);
+
+    let changed: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _, _))| *op != Op::Equal)
+        .map(|(k, _)| k)
+        .collect();
+    if changed.is_empty() {
+        return None;
+    }
+
+    // Merge changes whose context windows overlap into hunks of op ranges
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &k in &changed {
+        let start = k.saturating_sub(CONTEXT);
+        let end = (k + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut lines = vec!["--- expected".to_string(), "+++ actual".to_string()];
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let a_count = hunk.iter().filter(|(op, _, _)| *op != Op::Insert).count();
+        let b_count = hunk.iter().filter(|(op, _, _)| *op != Op::Delete).count();
+        let (_, a_start, b_start) = hunk[0];
+        lines.push(format!(
+            "@@ -{},{} +{},{} @@",
+            a_start + 1,
+            a_count,
+            b_start + 1,
+            b_count
+        ));
+        for &(op, i, j) in hunk {
+            lines.push(match op {
+                Op::Equal => format!(" {}", a[i]),
+                Op::Delete => format!("-{}", a[i]),
+                Op::Insert => format!("+{}", b[j]),
+            });
+        }
+    }
+
+    if lines.len() > max_lines {
+        let remaining = lines.len() - max_lines;
+        lines.truncate(max_lines);
+        lines.push(format!("... (diff truncated, {} more lines)", remaining));
+    }
+    Some(lines.join("\n"))
+}
@@ -1,141 +1,1039 @@
+use crate::compiler::Artifact;
+use crate::sandbox::Sandbox;
 use crate::types::*;
 use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::process::Command as TokioCommand;
 use tokio::time::sleep;
-use sysinfo::{Pid, ProcessRefreshKind, RefreshKind, System};
+#[cfg(not(target_os = "linux"))]
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use tempfile::TempDir;
+
+/// Error reported when a submission is killed for running past its time limit
+pub const TIME_LIMIT_EXCEEDED: &str = "Time limit exceeded";
+
+/// Error reported when a submission is killed for exceeding its memory limit
+pub const MEMORY_LIMIT_EXCEEDED: &str = "Memory limit exceeded";
+
+/// Error reported when a submission is killed for printing too much to stdout
+pub const OUTPUT_LIMIT_EXCEEDED: &str = "Output limit exceeded";
+
+/// Error reported when a submission is killed for neither printing nor using CPU for too long
+pub const IDLENESS_LIMIT_EXCEEDED: &str = "Idleness limit exceeded";
+
+/// Input file of an `IoMode::Files` run, in its working directory
+pub const INPUT_FILE: &str = "input.txt";
+
+/// Answer file of an `IoMode::Files` run, read back as its output
+pub const OUTPUT_FILE: &str = "output.txt";
+
+/// Environment variable carrying a test case's `seed` to the program and the checker
+pub const SEED_ENV_VAR: &str = "JUDGE_SEED";
+
+/// Stdout captured per run unless overridden (64 MB)
+pub const DEFAULT_OUTPUT_LIMIT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Prefix of UBSan diagnostics; UBSan keeps running afterwards, so the exit status alone misses them
+pub const UBSAN_MARKER: &str = ": runtime error: ";
+
+/// `error` of a run that exited cleanly after UBSan reported on stderr
+pub const UNDEFINED_BEHAVIOR: &str = "Undefined behavior detected";
+
+/// Memory polling period unless the problem configures one
+pub const DEFAULT_SAMPLING_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Processes (and threads) a submission may have at once unless the problem says otherwise
+pub const DEFAULT_PROCESS_LIMIT: u64 = 16;
+
+/// What a program reads on stdin
+pub enum ProgramInput<'a> {
+    Text(&'a str),
+    /// Redirected straight from the file, so large inputs are never held in memory
+    File(PathBuf),
+}
+
+impl ProgramInput<'_> {
+    /// The input as a file for helper programs, writing text to `scratch` first
+    pub(crate) async fn to_path(&self, scratch: PathBuf) -> std::io::Result<PathBuf> {
+        match self {
+            ProgramInput::Text(text) => {
+                tokio::fs::write(&scratch, text).await?;
+                Ok(scratch)
+            }
+            ProgramInput::File(path) => Ok(path.clone()),
+        }
+    }
+}
+
+/// Set a hard and soft resource limit in the child before it execs
+#[cfg(unix)]
+fn set_rlimit(cmd: &mut tokio::process::Command, resource: i32, value: u64) {
+    set_rlimits(cmd, resource, value, value);
+}
+
+/// `set_rlimit` with a soft limit below the hard one
+#[cfg(unix)]
+fn set_rlimits(cmd: &mut tokio::process::Command, resource: i32, soft: u64, hard: u64) {
+    // SAFETY: the closure only calls the async-signal-safe setrlimit
+    unsafe {
+        cmd.pre_exec(move || {
+            let limit = libc::rlimit {
+                rlim_cur: soft as libc::rlim_t,
+                rlim_max: hard as libc::rlim_t,
+            };
+            if libc::setrlimit(resource as _, &limit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Cap the child's virtual address space so runaway allocations fail inside
+/// the child instead of destabilising the host
+#[cfg(unix)]
+pub(crate) fn limit_address_space(cmd: &mut tokio::process::Command, limit_bytes: u64) {
+    set_rlimit(cmd, libc::RLIMIT_AS as i32, limit_bytes);
+}
+
+/// Cap the CPU time of the child (and of each process it starts). Past it the kernel sends
+/// SIGXCPU, so the cause is recognisable; SIGKILL follows a second later if that is ignored
+#[cfg(unix)]
+pub(crate) fn limit_cpu_time(cmd: &mut tokio::process::Command, secs: u64) {
+    let secs = secs.max(1);
+    set_rlimits(cmd, libc::RLIMIT_CPU as i32, secs, secs + 1);
+}
+
+/// Cap the size of any file the child writes. One byte past `OUTPUT_FILE`'s limit is
+/// allowed so the overflow is still seen; the write after that gets SIGXFSZ (or EFBIG)
+#[cfg(unix)]
+fn limit_file_size(cmd: &mut tokio::process::Command, output_limit: u64) {
+    set_rlimit(cmd, libc::RLIMIT_FSIZE as i32, output_limit.saturating_add(1));
+}
+
+/// Pin the child, and everything it starts, to one CPU core so the scheduler can't
+/// migrate it mid-run. A core that doesn't exist makes the spawn fail with EINVAL.
+#[cfg(target_os = "linux")]
+fn pin_to_core(cmd: &mut tokio::process::Command, core: usize) {
+    // SAFETY: an all-zero cpu_set_t is the empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let in_range = core < 8 * std::mem::size_of::<libc::cpu_set_t>();
+    if in_range {
+        // SAFETY: `core` is within the set, checked above
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+    // SAFETY: the closure only calls the async-signal-safe sched_setaffinity
+    unsafe {
+        cmd.pre_exec(move || {
+            if !in_range || libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Tasks (processes and threads) currently owned by our real uid, judge included
+#[cfg(target_os = "linux")]
+fn tasks_owned_by_current_user() -> u64 {
+    // SAFETY: getuid cannot fail
+    let uid = unsafe { libc::getuid() }.to_string();
+    let Ok(entries) = std::fs::read_dir("/proc") else { return 0 };
+    entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+        .filter_map(|e| std::fs::read_to_string(e.path().join("status")).ok())
+        .filter(|status| {
+            status
+                .lines()
+                .find_map(|l| l.strip_prefix("Uid:"))
+                .and_then(|ids| ids.split_whitespace().next())
+                == Some(uid.as_str())
+        })
+        .filter_map(|status| status.lines().find_map(|l| l.strip_prefix("Threads:"))?.trim().parse::<u64>().ok())
+        .sum()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn tasks_owned_by_current_user() -> u64 {
+    0
+}
+
+/// How stale `uid_tasks` may get before a rescan is started
+#[cfg(unix)]
+const UID_TASKS_MAX_AGE: Duration = Duration::from_secs(1);
+
+/// `tasks_owned_by_current_user` as of at most `UID_TASKS_MAX_AGE` ago. Only the first call
+/// scans `/proc` itself; after that a stale count is returned at once while a background
+/// thread rescans, so runs never wait on the scan
+#[cfg(unix)]
+fn uid_tasks() -> u64 {
+    static SCANNED: std::sync::Mutex<Option<(u64, Instant)>> = std::sync::Mutex::new(None);
+    static SCANNING: AtomicBool = AtomicBool::new(false);
+    let rescan = || {
+        let count = tasks_owned_by_current_user();
+        *SCANNED.lock().unwrap() = Some((count, Instant::now()));
+        count
+    };
+    let scanned = *SCANNED.lock().unwrap();
+    match scanned {
+        None => rescan(),
+        Some((count, at)) => {
+            if at.elapsed() > UID_TASKS_MAX_AGE && !SCANNING.swap(true, Ordering::Relaxed) {
+                std::thread::spawn(move || {
+                    rescan();
+                    SCANNING.store(false, Ordering::Relaxed);
+                });
+            }
+            count
+        }
+    }
+}
+
+/// Cap how many more processes/threads the child can start, so a fork bomb fails with EAGAIN.
+/// RLIMIT_NPROC counts every task of the real uid, so the cap is `max` on top of what the uid
+/// already runs (root ignores it entirely). Only the run's own headroom is granted: a run can
+/// never start more than `max` tasks, though concurrent runs may leave each other less
+#[cfg(unix)]
+pub(crate) fn limit_processes(cmd: &mut tokio::process::Command, max: u64) {
+    set_rlimit(cmd, libc::RLIMIT_NPROC as i32, uid_tasks() + max);
+}
+
+/// Block until `pid` has exited, leaving it as a zombie so its final stats stay readable.
+/// Returns the kernel's peak RSS in KB for the process and the children it waited for.
+#[cfg(target_os = "linux")]
+pub(crate) fn wait_for_exit_without_reaping(pid: u32) -> Option<u64> {
+    // SAFETY: plain syscall on zeroed out-parameters. The raw syscall takes a fifth
+    // `rusage` argument that the libc wrapper hides; it is filled in even with WNOWAIT.
+    unsafe {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let mut usage: libc::rusage = std::mem::zeroed();
+        let rc = libc::syscall(
+            libc::SYS_waitid,
+            libc::P_PID,
+            pid as libc::id_t,
+            &mut info as *mut libc::siginfo_t,
+            libc::WEXITED | libc::WNOWAIT,
+            &mut usage as *mut libc::rusage,
+        );
+        (rc == 0).then_some(usage.ru_maxrss as u64)
+    }
+}
+
+/// Peak RSS in KB of a still-running `pid` (`VmHWM` in `/proc/<pid>/status`)
+#[cfg(target_os = "linux")]
+fn read_peak_rss_kb(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_peak_rss_kb(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// User + system CPU time of `pid` (including children it has waited for), from `/proc/<pid>/stat`
+#[cfg(target_os = "linux")]
+pub(crate) fn read_cpu_time_ms(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // Fields after the parenthesised command name start at field 3 (state)
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let ticks: u64 = fields.get(11..15)?.iter().filter_map(|f| f.parse::<u64>().ok()).sum();
+    // SAFETY: sysconf has no preconditions
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+    Some(ticks * 1000 / ticks_per_sec)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn read_cpu_time_ms(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Host variables `set_environment` passes through: `PATH` so interpreters (and wrappers
+/// around them) can still be found, and on Windows the system root, without which
+/// process startup and the CRT fail
+#[cfg(not(windows))]
+const KEPT_ENV_VARS: &[&str] = &["PATH"];
+#[cfg(windows)]
+const KEPT_ENV_VARS: &[&str] = &["PATH", "SystemRoot", "SystemDrive"];
+
+/// Replace the inherited environment with `envs`, so host secrets never reach a submission.
+/// Only `KEPT_ENV_VARS` survive from the host.
+pub(crate) fn set_environment(cmd: &mut tokio::process::Command, envs: &[(String, String)]) {
+    cmd.env_clear();
+    for &name in KEPT_ENV_VARS {
+        if let Some(value) = std::env::var_os(name) {
+            cmd.env(name, value);
+        }
+    }
+    cmd.envs(envs.iter().map(|(k, v)| (k, v)));
+}
+
+/// Resolve once `activity()` has returned the same value for `limit`; never resolves without a limit.
+/// Callers sample output progress and CPU time, so a run blocked on a read or a deadlock trips it.
+/// Off Linux `read_cpu_time_ms` has nothing to report and a compute-bound run would look idle,
+/// so the limit is ignored there.
+pub(crate) async fn idle_watchdog<T: PartialEq>(limit: Option<Duration>, mut activity: impl FnMut() -> T) {
+    let Some(limit) = limit.filter(|_| cfg!(target_os = "linux")) else {
+        return std::future::pending().await;
+    };
+    let poll = (limit / 4).clamp(Duration::from_millis(10), Duration::from_millis(100));
+    let mut last = activity();
+    let mut idle_since = Instant::now();
+    loop {
+        sleep(poll).await;
+        let current = activity();
+        if current != last {
+            last = current;
+            idle_since = Instant::now();
+        } else if idle_since.elapsed() >= limit {
+            return;
+        }
+    }
+}
+
+/// Resident memory in KB of `root` and all of its descendants, found through
+/// `/proc/<pid>/task/*/children` so no other process is read; `None` once `root` is gone
+#[cfg(target_os = "linux")]
+fn subtree_memory_kb(root: u32) -> Option<u64> {
+    // SAFETY: sysconf has no preconditions
+    let page_kb = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1024) as u64 / 1024;
+    // Threads share their process's memory, so only processes are summed
+    let resident_kb = |pid: u32| -> Option<u64> {
+        let statm = std::fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+        Some(statm.split_whitespace().nth(1)?.parse::<u64>().ok()? * page_kb)
+    };
+    let mut total = resident_kb(root)?;
+    let mut pending = vec![root];
+    while let Some(pid) = pending.pop() {
+        if pid != root {
+            total += resident_kb(pid).unwrap_or(0);
+        }
+        let Ok(tasks) = std::fs::read_dir(format!("/proc/{}/task", pid)) else { continue };
+        for task in tasks.flatten() {
+            let children = std::fs::read_to_string(task.path().join("children")).unwrap_or_default();
+            pending.extend(children.split_whitespace().filter_map(|child| child.parse::<u32>().ok()));
+        }
+    }
+    Some(total)
+}
+
+/// Resident memory in KB of `root` alone; `None` once it is gone
+#[cfg(not(target_os = "linux"))]
+fn subtree_memory_kb(root: u32) -> Option<u64> {
+    let mut sys = System::new();
+    let pid = Pid::from_u32(root);
+    sys.refresh_process_specifics(pid, ProcessRefreshKind::new().with_memory());
+    Some(sys.process(pid)?.memory() / 1024) // bytes -> KB
+}
+
+/// Peak resident memory of a process tree, polled in the background until `finish`
+pub(crate) struct MemorySampler {
+    peak_kb: Arc<AtomicU64>,
+    running: Arc<AtomicBool>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MemorySampler {
+    /// Start polling `pid` and its descendants every `interval`; with no interval only
+    /// the values passed to `record` count
+    pub(crate) fn start(pid: Option<u32>, interval: Option<Duration>) -> Self {
+        let peak_kb = Arc::new(AtomicU64::new(0));
+        let running = Arc::new(AtomicBool::new(true));
+        let (peak_clone, running_clone) = (Arc::clone(&peak_kb), Arc::clone(&running));
+        let task = tokio::spawn(async move {
+          if let (Some(pid_val), Some(poll_interval)) = (pid, interval) {
+            while running_clone.load(Ordering::Relaxed) {
+              if let Some(mem) = subtree_memory_kb(pid_val) {
+                peak_clone.fetch_max(mem, Ordering::Relaxed);
+              } else {
+                // Gone without `running` being cleared: the run was abandoned
+                break;
+              }
+              sleep(poll_interval).await;
+            }
+          }
+        });
+        Self { peak_kb, running, task }
+    }
+
+    /// Fold in a peak measured some other way, e.g. the kernel's high-water mark
+    pub(crate) fn record(&self, kb: u64) {
+        self.peak_kb.fetch_max(kb, Ordering::Relaxed);
+    }
+
+    /// Stop polling and return the peak in KB
+    pub(crate) async fn finish(self) -> u64 {
+        self.running.store(false, Ordering::Relaxed);
+        let _ = self.task.await;
+        self.peak_kb.load(Ordering::Relaxed)
+    }
+}
+
+/// Fresh working directory for a file I/O run, with the input written to `INPUT_FILE`
+async fn prepare_io_dir(input: &ProgramInput<'_>) -> Result<TempDir> {
+    let dir = TempDir::new().context("Failed to create run directory")?;
+    let input_path = dir.path().join(INPUT_FILE);
+    match input {
+        ProgramInput::Text(text) => tokio::fs::write(&input_path, text).await,
+        ProgramInput::File(path) => tokio::fs::copy(path, &input_path).await.map(|_| ()),
+    }
+    .context("Failed to write input file")?;
+    Ok(dir)
+}
+
+/// `OUTPUT_FILE` of a file I/O run, read at most one byte past the output limit;
+/// empty if the program never created it
+async fn read_output_file(dir: &Path, output_limit: u64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if let Ok(file) = tokio::fs::File::open(dir.join(OUTPUT_FILE)).await {
+        let _ = file.take(output_limit + 1).read_to_end(&mut buf).await;
+    }
+    buf
+}
+
+/// "Exited with code N" or "Killed by signal N", the `error` of a run that failed on its own
+pub(crate) fn describe_exit(status: &std::process::ExitStatus) -> String {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(status) {
+        return format!("Killed by signal {}", signal);
+    }
+    match status.code() {
+        Some(code) => format!("Exited with code {}", code),
+        None => "Exited abnormally".to_string(),
+    }
+}
+
+/// Why `execute` stopped waiting for the child
+enum Stop {
+    Exited(std::io::Result<std::process::ExitStatus>, Option<u64>),
+    OutputExceeded,
+    Idle,
+}
+
+/// SIGKILL every process in the group led by `pid`, including descendants that outlive it
+pub(crate) fn kill_process_group(pid: u32) {
+    // SAFETY: killpg has no memory-safety preconditions
+    #[cfg(unix)]
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Windows stand-in for rlimits and process groups: a Job Object holding the submission's
+/// process tree. Closing the job (on drop) kills every process still in it.
+#[cfg(windows)]
+pub(crate) struct Job(windows_sys::Win32::Foundation::HANDLE);
+
+// SAFETY: a job handle is a kernel object reference, usable from any thread
+#[cfg(windows)]
+unsafe impl Send for Job {}
+#[cfg(windows)]
+unsafe impl Sync for Job {}
+
+#[cfg(windows)]
+impl Job {
+    /// Create a kill-on-close job; with a limit, commits beyond `memory_limit_bytes` across
+    /// the whole job fail, which surfaces as an out-of-memory crash
+    pub(crate) fn new(memory_limit_bytes: Option<u64>) -> std::io::Result<Self> {
+        use windows_sys::Win32::System::JobObjects::*;
+        // SAFETY: null attributes/name are allowed; `info` outlives the call and its size is passed
+        unsafe {
+            let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if handle.is_null() {
+                return Err(std::io::Error::last_os_error());
+            }
+            let job = Job(handle);
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            if let Some(bytes) = memory_limit_bytes {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+                info.JobMemoryLimit = bytes as usize;
+            }
+            let set = SetInformationJobObject(
+                handle,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const std::ffi::c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+            if set == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(job)
+        }
+    }
+
+    /// Have `cmd` start its process suspended, so it can join a job before running any code
+    pub(crate) fn suspend_on_spawn(cmd: &mut tokio::process::Command) {
+        cmd.creation_flags(windows_sys::Win32::System::Threading::CREATE_SUSPENDED);
+    }
+
+    /// Put a child spawned with `suspend_on_spawn` into the job, then let it run; nothing it
+    /// starts can escape the job's limits or its kill. On failure the child stays suspended
+    /// until it is killed
+    pub(crate) fn assign(&self, child: &tokio::process::Child) -> std::io::Result<()> {
+        let (Some(process), Some(pid)) = (child.raw_handle(), child.id()) else {
+            return Ok(()); // already exited and reaped
+        };
+        // SAFETY: both handles are valid for the duration of the call
+        if unsafe { windows_sys::Win32::System::JobObjects::AssignProcessToJobObject(self.0, process as _) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        resume_threads(pid)
+    }
+}
+
+/// Resume the threads of a process created suspended (just its primary thread)
+#[cfg(windows)]
+fn resume_threads(pid: u32) -> std::io::Result<()> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32};
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+    // SAFETY: the snapshot and thread handles are checked before use and closed once;
+    // `entry` is sized as the API requires
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+        let mut resumed = false;
+        let mut more = Thread32First(snapshot, &mut entry) != 0;
+        while more {
+            if entry.th32OwnerProcessID == pid {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if !thread.is_null() {
+                    resumed |= ResumeThread(thread) != u32::MAX;
+                    CloseHandle(thread);
+                }
+            }
+            more = Thread32Next(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+        if resumed {
+            Ok(())
+        } else {
+            Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no thread of the process could be resumed"))
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Job {
+    fn drop(&mut self) {
+        // SAFETY: the handle is owned by this struct and closed exactly once
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+/// Kills the child's process group if `execute` is dropped mid-run (e.g. a cancelled judge)
+struct GroupGuard(Option<u32>);
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.0 {
+            kill_process_group(pid);
+        }
+    }
+}
 
 /// Handles execution of compiled code with sandboxing
 pub struct Executor {
     time_limit: Duration,
-    _memory_limit: u64, // reserved for future use
+    memory_limit: u64, // in MB, 0 = unlimited
+    address_space_limit: bool,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+    detect_ubsan: bool,
+    time_limit_mode: TimeLimitMode,
+    output_limit: u64, // in bytes
+    sandboxed: bool,
+    allow_network: bool,
+    process_limit: Option<u64>,
+    idle_limit: Option<Duration>,
+    memory_sampling: MemorySampling,
+    io_mode: IoMode,
+    cpu_core: Option<usize>,
 }
 
 impl Executor {
     pub fn new(time_limit_ms: u64, memory_limit_mb: u64) -> Self {
         Self {
             time_limit: Duration::from_millis(time_limit_ms),
-            _memory_limit: memory_limit_mb,
+            memory_limit: memory_limit_mb,
+            address_space_limit: true,
+            args: Vec::new(),
+            envs: Vec::new(),
+            detect_ubsan: false,
+            time_limit_mode: TimeLimitMode::Wall,
+            output_limit: DEFAULT_OUTPUT_LIMIT_BYTES,
+            sandboxed: true,
+            allow_network: false,
+            process_limit: Some(DEFAULT_PROCESS_LIMIT),
+            idle_limit: None,
+            memory_sampling: MemorySampling::default(),
+            io_mode: IoMode::Stdio,
+            cpu_core: None,
         }
     }
 
+    /// Run the child on this CPU core only (Linux; ignored elsewhere), for steadier timings
+    pub fn with_cpu_affinity(mut self, core: Option<usize>) -> Self {
+        self.cpu_core = core;
+        self
+    }
+
+    /// Exchange data through stdin/stdout (default) or through files in a fresh working directory
+    pub fn with_io_mode(mut self, mode: IoMode) -> Self {
+        self.io_mode = mode;
+        self
+    }
+
+    /// Choose how peak memory is measured (polling by default)
+    pub fn with_memory_sampling(mut self, sampling: MemorySampling) -> Self {
+        self.memory_sampling = sampling;
+        self
+    }
+
+    /// Kill the child once it goes this long without new stdout or CPU time; `None` disables it.
+    /// Only enforced on Linux, where CPU time can be read
+    pub fn with_idle_limit(mut self, limit_ms: Option<u64>) -> Self {
+        self.idle_limit = limit_ms.map(Duration::from_millis);
+        self
+    }
+
+    /// Cap the number of processes/threads the child may run; `None` leaves it unlimited
+    pub fn with_process_limit(mut self, max: Option<u64>) -> Self {
+        self.process_limit = max;
+        self
+    }
+
+    /// Let the child open network connections (blocked by default)
+    pub fn with_network(mut self, allowed: bool) -> Self {
+        self.allow_network = allowed;
+        self
+    }
+
+    /// Toggle the namespace + seccomp jail and private root (only applied where the host supports it)
+    pub fn with_sandbox(mut self, enabled: bool) -> Self {
+        self.sandboxed = enabled;
+        self
+    }
+
+    /// Kill the child once it writes more than `bytes` to stdout
+    pub fn with_output_limit(mut self, bytes: u64) -> Self {
+        self.output_limit = bytes;
+        self
+    }
+
+    /// Choose whether the time limit is measured in wall-clock or CPU time
+    pub fn with_time_limit_mode(mut self, mode: TimeLimitMode) -> Self {
+        self.time_limit_mode = mode;
+        self
+    }
+
+    /// Toggle the hard address-space cap. Runtimes that reserve large virtual
+    /// ranges up front (e.g. the JVM) must disable it and rely on their own limits.
+    pub fn with_address_space_limit(mut self, enabled: bool) -> Self {
+        self.address_space_limit = enabled;
+        self
+    }
+
+    /// Pass command-line arguments to the program
+    pub fn with_args(mut self, args: impl IntoIterator<Item = String>) -> Self {
+        self.args.extend(args);
+        self
+    }
+
+    /// Set an environment variable for the child process; nothing else is inherited except `PATH`
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    /// Treat UBSan diagnostics on stderr as a failed run even when the program exits cleanly
+    pub fn with_ubsan_detection(mut self, enabled: bool) -> Self {
+        self.detect_ubsan = enabled;
+        self
+    }
+
     /// Execute the compiled program with given input
-    pub async fn execute(&self, executable_path: &str, input: &str) -> Result<ExecutionResult> {
+    pub async fn execute(&self, artifact: &Artifact, input: &str) -> Result<ExecutionResult> {
+        self.execute_input(artifact, &ProgramInput::Text(input)).await
+    }
+
+    /// Execute the compiled program, feeding stdin from text or a file
+    pub async fn execute_input(&self, artifact: &Artifact, input: &ProgramInput<'_>) -> Result<ExecutionResult> {
         let start_time = Instant::now();
-        
+
+        // File I/O runs get their own directory holding the input file, removed on return
+        let io_dir = match self.io_mode {
+            IoMode::Stdio => None,
+            IoMode::Files => Some(prepare_io_dir(input).await?),
+        };
+
         // Start the process using tokio
-        let mut child = TokioCommand::new(executable_path)
-            .stdin(Stdio::piped())
+        let mut cmd = artifact.command();
+        match (&io_dir, input) {
+            (Some(dir), _) => cmd.current_dir(dir.path()).stdin(Stdio::null()),
+            (None, ProgramInput::Text(_)) => cmd.stdin(Stdio::piped()),
+            (None, ProgramInput::File(path)) => cmd.stdin(std::fs::File::open(path).context("Failed to open test input")?),
+        };
+        cmd.args(&self.args)
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Failed to start process")?;
-
-        // Send input to stdin
-        if let Some(stdin) = child.stdin.as_mut() {
-            stdin.write_all(input.as_bytes()).await
-                .context("Failed to write to stdin")?;
+            .stderr(Stdio::piped());
+        set_environment(&mut cmd, &self.envs);
+        // Own process group, so everything the submission spawns can be killed together
+        #[cfg(unix)]
+        cmd.process_group(0);
+        cmd.kill_on_drop(true);
+        #[cfg(unix)]
+        if self.address_space_limit && self.memory_limit > 0 {
+            limit_address_space(&mut cmd, self.memory_limit * 1024 * 1024);
         }
+        #[cfg(unix)]
+        if let Some(max) = self.process_limit {
+            limit_processes(&mut cmd, max);
+        }
+        // Otherwise a file I/O run could fill the disk before its output is read back
+        #[cfg(unix)]
+        if io_dir.is_some() {
+            limit_file_size(&mut cmd, self.output_limit);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(core) = self.cpu_core {
+            pin_to_core(&mut cmd, core);
+        }
+        if self.sandboxed {
+            Sandbox::confine(&mut cmd, self.allow_network, artifact.run_files().as_slice());
+        }
+        #[cfg(windows)]
+        Job::suspend_on_spawn(&mut cmd);
+        let mut child = cmd.spawn().context("Failed to start process")?;
+        // Held until `execute` returns (or is dropped), then kills whatever the submission left running
+        #[cfg(windows)]
+        let _job = {
+            let memory_limit = (self.address_space_limit && self.memory_limit > 0).then_some(self.memory_limit * 1024 * 1024);
+            let job = Job::new(memory_limit).context("Failed to create job object")?;
+            job.assign(&child).context("Failed to assign process to job object")?;
+            job
+        };
 
-        let pid = child.id();
-        let peak_mem = Arc::new(AtomicU64::new(0));
-        let running = Arc::new(AtomicBool::new(true));
-
-        // Sampling task to capture peak memory while the process is running
-        let peak_mem_clone = Arc::clone(&peak_mem);
-        let running_clone = Arc::clone(&running);
-        let sampler = tokio::spawn(async move {
-          if let Some(pid_val) = pid {
-            let mut sys = System::new_with_specifics(
-              RefreshKind::new().with_processes(ProcessRefreshKind::new())
-            );
-            let target_pid = Pid::from_u32(pid_val as u32);
-            while running_clone.load(Ordering::Relaxed) {
-              sys.refresh_process_specifics(target_pid, ProcessRefreshKind::new());
-              if let Some(proc) = sys.process(target_pid) {
-                let mem = proc.memory(); // in KB
-                let current = peak_mem_clone.load(Ordering::Relaxed);
-                if mem > current {
-                  peak_mem_clone.store(mem, Ordering::Relaxed);
-                }
-              }
-              sleep(Duration::from_millis(30)).await;
-            }
-          }
-        });
-
-        // Concurrently read stdout/stderr while waiting
+        // Drain stdout/stderr before feeding stdin so a chatty child can never
+        // block on a full pipe while we are still writing its input
+        // Both streams are read at most one byte past the output limit, so a program
+        // printing forever can't exhaust the judge's own memory
         let mut stdout_opt = child.stdout.take();
         let mut stderr_opt = child.stderr.take();
+        let output_limit = self.output_limit;
+        let output_exceeded = Arc::new(tokio::sync::Notify::new());
 
+        let output_exceeded_clone = Arc::clone(&output_exceeded);
+        // Bytes read so far, watched by the idleness limit
+        let stdout_bytes = Arc::new(AtomicU64::new(0));
+        let stdout_bytes_clone = Arc::clone(&stdout_bytes);
         let stdout_task = tokio::spawn(async move {
-            if let Some(mut s) = stdout_opt.take() {
+            if let Some(s) = stdout_opt.take() {
                 let mut buf = Vec::new();
-                let _ = s.read_to_end(&mut buf).await;
+                let mut s = s.take(output_limit + 1);
+                while let Ok(1..) = s.read_buf(&mut buf).await {
+                    stdout_bytes_clone.store(buf.len() as u64, Ordering::Relaxed);
+                }
+                if buf.len() as u64 > output_limit {
+                    output_exceeded_clone.notify_one();
+                }
                 buf
             } else { Vec::new() }
         });
+        // Stderr past the limit is read and discarded, so a child logging heavily
+        // neither blocks on a full pipe nor dies writing to a closed one
         let stderr_task = tokio::spawn(async move {
             if let Some(mut s) = stderr_opt.take() {
                 let mut buf = Vec::new();
-                let _ = s.read_to_end(&mut buf).await;
+                let _ = (&mut s).take(output_limit).read_to_end(&mut buf).await;
+                let _ = tokio::io::copy(&mut s, &mut tokio::io::sink()).await;
                 buf
             } else { Vec::new() }
         });
 
-        // Wait with timeout so we can kill runaway processes quickly
-        let wait_result = tokio::time::timeout(self.time_limit, child.wait()).await;
+        // Feed stdin concurrently; dropping the handle closes the pipe so the child sees EOF.
+        // A file input is already attached, so there is no pipe to feed
+        let stdin_opt = child.stdin.take();
+        let input_bytes = match input {
+            ProgramInput::Text(text) => text.as_bytes().to_vec(),
+            ProgramInput::File(_) => Vec::new(),
+        };
+        let stdin_task = tokio::spawn(async move {
+            if let Some(mut stdin) = stdin_opt {
+                // A child that exits without reading all input closes the pipe early; that's not our error
+                let _ = stdin.write_all(&input_bytes).await;
+            }
+        });
+
+        let pid = child.id();
+        let mut group_guard = GroupGuard(pid);
+
+        // Sampling task to capture peak memory while the process is running; with
+        // `PeakAtExit` on Linux the kernel's high-water mark is read at exit instead
+        let poll_interval = match self.memory_sampling {
+            MemorySampling::Poll { interval_ms } => Some(Duration::from_millis(interval_ms.max(1))),
+            MemorySampling::PeakAtExit if cfg!(target_os = "linux") => None,
+            MemorySampling::PeakAtExit => Some(DEFAULT_SAMPLING_INTERVAL),
+        };
+        let sampler = MemorySampler::start(pid, poll_interval);
+
+        // In CPU mode a sleeping/blocked process burns no CPU, so the wall clock
+        // only acts as a generous backstop
+        let wall_limit = match self.time_limit_mode {
+            TimeLimitMode::Wall => self.time_limit,
+            TimeLimitMode::Cpu => self.time_limit * 2 + Duration::from_secs(1),
+        };
+
+        // Wait with timeout so we can kill runaway processes quickly. CPU time is read
+        // while the exited child is still a zombie, i.e. before `wait` reaps it.
+        let wait_result = tokio::time::timeout(wall_limit, async {
+            tokio::select! {
+                result = async {
+                    #[cfg(target_os = "linux")]
+                    if let Some(pid) = pid {
+                        if let Ok(Some(kb)) = tokio::task::spawn_blocking(move || wait_for_exit_without_reaping(pid)).await {
+                            sampler.record(kb);
+                        }
+                        // The unreaped leader pins the group id, so this can't hit a recycled group
+                        kill_process_group(pid);
+                    }
+                    let cpu_time_ms = pid.and_then(read_cpu_time_ms);
+                    let status = child.wait().await;
+                    #[cfg(not(target_os = "linux"))]
+                    if let Some(pid) = pid {
+                        kill_process_group(pid);
+                    }
+                    Stop::Exited(status, cpu_time_ms)
+                } => result,
+                _ = output_exceeded.notified() => Stop::OutputExceeded,
+                _ = idle_watchdog(self.idle_limit, || {
+                    (stdout_bytes.load(Ordering::Relaxed), pid.and_then(read_cpu_time_ms))
+                }) => Stop::Idle,
+            }
+        })
+        .await;
         let execution_time = start_time.elapsed().as_millis() as u64;
+        // From here on every branch kills the group itself
+        group_guard.0 = None;
 
         match wait_result {
-            Ok(Ok(status)) => {
+            Ok(stop @ (Stop::OutputExceeded | Stop::Idle)) => {
+                let cpu_time_ms = pid.and_then(read_cpu_time_ms);
+                if let Some(kb) = pid.and_then(read_peak_rss_kb) {
+                    sampler.record(kb);
+                }
+                if let Some(pid) = pid {
+                    kill_process_group(pid);
+                }
+                let _ = child.kill().await;
+                let _ = child.wait().await;
+                stdin_task.abort();
+                let _ = stdout_task.await;
+                let stderr = stderr_task.await.unwrap_or_default();
+                let memory_usage = sampler.finish().await;
+
+                let error = if matches!(stop, Stop::Idle) { IDLENESS_LIMIT_EXCEEDED } else { OUTPUT_LIMIT_EXCEEDED };
+                Ok(ExecutionResult {
+                    success: false,
+                    output: String::new(),
+                    output_bytes: Vec::new(),
+                    error: Some(error.to_string()),
+                    stderr: String::from_utf8_lossy(&stderr).to_string(),
+                    execution_time,
+                    cpu_time_ms,
+                    memory_usage,
+                })
+            }
+            Ok(Stop::Exited(Ok(_), Some(cpu_time_ms)))
+                if self.time_limit_mode == TimeLimitMode::Cpu
+                    && cpu_time_ms > self.time_limit.as_millis() as u64 =>
+            {
+                let _ = stdout_task.await;
+                let stderr = stderr_task.await.unwrap_or_default();
+                let memory_usage = sampler.finish().await;
+
+                Ok(ExecutionResult {
+                    success: false,
+                    output: String::new(),
+                    output_bytes: Vec::new(),
+                    error: Some(TIME_LIMIT_EXCEEDED.to_string()),
+                    stderr: String::from_utf8_lossy(&stderr).to_string(),
+                    execution_time,
+                    cpu_time_ms: Some(cpu_time_ms),
+                    memory_usage,
+                })
+            }
+            Ok(Stop::Exited(Ok(status), cpu_time_ms)) => {
+                let _ = stdin_task.await;
                 let stdout_buf = stdout_task.await.unwrap_or_default();
+                // In file mode the answer is the output file; console output is ignored
+                let stdout_buf = match &io_dir {
+                    Some(dir) => read_output_file(dir.path(), self.output_limit).await,
+                    None => stdout_buf,
+                };
                 let stderr_buf = stderr_task.await.unwrap_or_default();
-                let output_str = String::from_utf8_lossy(&stdout_buf).to_string();
-                let error = if !status.success() && !stderr_buf.is_empty() {
-                    Some(String::from_utf8_lossy(&stderr_buf).to_string())
+                let stderr_str = String::from_utf8_lossy(&stderr_buf).to_string();
+                let memory_usage = sampler.finish().await;
+
+                // A failed allocation under the cap shows as a crash while measured memory sits
+                // near the limit; what the program prints is never trusted for the verdict
+                let memory_limit_kb = self.memory_limit * 1024;
+                let out_of_memory = !status.success() && memory_limit_kb > 0 && memory_usage * 10 >= memory_limit_kb * 9;
+                let ubsan_report = self.detect_ubsan && stderr_str.contains(UBSAN_MARKER);
+                let success = status.success() && !ubsan_report;
+                // The child may exit right after overflowing, before the limit watcher fires
+                let output_exceeded = stdout_buf.len() as u64 > self.output_limit;
+                let success = success && !output_exceeded;
+                let error = if output_exceeded {
+                    Some(OUTPUT_LIMIT_EXCEEDED.to_string())
+                } else if out_of_memory {
+                    Some(MEMORY_LIMIT_EXCEEDED.to_string())
+                } else if !status.success() {
+                    Some(describe_exit(&status))
+                } else if ubsan_report {
+                    Some(UNDEFINED_BEHAVIOR.to_string())
                 } else { None };
-                running.store(false, Ordering::Relaxed);
-                let _ = sampler.await;
-                let memory_usage = peak_mem.load(Ordering::Relaxed);
 
+                let stdout_buf = if output_exceeded { Vec::new() } else { stdout_buf };
                 Ok(ExecutionResult {
-                    success: status.success(),
-                    output: output_str,
+                    success,
+                    output: String::from_utf8_lossy(&stdout_buf).to_string(),
+                    output_bytes: stdout_buf,
                     error,
+                    stderr: stderr_str,
                     execution_time,
+                    cpu_time_ms,
                     memory_usage,
                 })
             }
-            Ok(Err(e)) => Ok(ExecutionResult {
+            Ok(Stop::Exited(Err(e), cpu_time_ms)) => Ok(ExecutionResult {
                 success: false,
                 output: String::new(),
+                output_bytes: Vec::new(),
                 error: Some(format!("Process error: {}", e)),
+                stderr: String::new(),
                 execution_time,
+                cpu_time_ms,
                 memory_usage: 0,
             }),
             Err(_) => {
                 // Timeout - ensure the process is killed and outputs are drained
+                let cpu_time_ms = pid.and_then(read_cpu_time_ms);
+                if let Some(kb) = pid.and_then(read_peak_rss_kb) {
+                    sampler.record(kb);
+                }
+                if let Some(pid) = pid {
+                    kill_process_group(pid);
+                }
                 let _ = child.kill().await;
                 let _ = child.wait().await;
+                stdin_task.abort();
                 let _ = stdout_task.await;
-                let _ = stderr_task.await;
-                running.store(false, Ordering::Relaxed);
-                let _ = sampler.await;
-                let memory_usage = peak_mem.load(Ordering::Relaxed);
+                let stderr = stderr_task.await.unwrap_or_default();
+                let memory_usage = sampler.finish().await;
 
                 Ok(ExecutionResult {
                     success: false,
                     output: String::new(),
-                    error: Some("Time limit exceeded".to_string()),
+                    output_bytes: Vec::new(),
+                    error: Some(TIME_LIMIT_EXCEEDED.to_string()),
+                    stderr: String::from_utf8_lossy(&stderr).to_string(),
                     execution_time,
+                    cpu_time_ms,
                     memory_usage,
                 })
             }
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn echoes_large_input_without_deadlock() {
+        let input = "0123456789abcdef".repeat(64 * 1024); // 1 MB, far past a pipe buffer
+        let cat = Artifact::Native { path: "/bin/cat".to_string() };
+        let result = Executor::new(5000, 256).execute(&cat, &input).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output, input);
+    }
+
+    /// `sh -c script`, with the shell as the artifact
+    fn shell(script: &str) -> Artifact {
+        Artifact::Interpreted {
+            path: "/bin/sh".to_string(),
+            argv: vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn endless_output_stops_at_the_limit() {
+        let start = Instant::now();
+        let executor = Executor::new(10_000, 256).with_output_limit(1024 * 1024).with_process_limit(None);
+        let result = executor.execute(&shell("yes"), "").await.unwrap();
+        assert_eq!(result.error.as_deref(), Some(OUTPUT_LIMIT_EXCEEDED));
+        assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+    }
+
+    #[tokio::test]
+    async fn stderr_past_the_limit_is_drained() {
+        let executor = Executor::new(10_000, 256).with_output_limit(1024 * 1024).with_process_limit(None);
+        let result = executor.execute(&shell("head -c 8000000 /dev/zero >&2 && echo done"), "").await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output, "done\n");
+        assert_eq!(result.stderr.len(), 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn file_output_stops_at_the_limit() {
+        let start = Instant::now();
+        let executor = Executor::new(10_000, 256)
+            .with_io_mode(IoMode::Files)
+            .with_output_limit(1024 * 1024)
+            .with_process_limit(None);
+        let result = executor.execute(&shell("yes > output.txt"), "").await.unwrap();
+        assert_eq!(result.error.as_deref(), Some(OUTPUT_LIMIT_EXCEEDED));
+        assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+    }
+
+    /// Whether any process runs with exactly this command line
+    #[cfg(target_os = "linux")]
+    fn running(cmdline: &[&str]) -> bool {
+        let wanted: Vec<u8> = cmdline.iter().flat_map(|arg| arg.bytes().chain([0])).collect();
+        std::fs::read_dir("/proc")
+            .unwrap()
+            .flatten()
+            .any(|entry| std::fs::read(entry.path().join("cmdline")).is_ok_and(|found| found == wanted))
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn forked_grandchildren_are_killed_with_the_run() {
+        // An odd duration marks this test's sleeper among other processes
+        let sleeper = ["sleep", "31.4159"];
+        let executor = Executor::new(10_000, 256).with_process_limit(None);
+        let start = Instant::now();
+        let result = executor.execute(&shell("(sleep 31.4159 &); echo started"), "").await.unwrap();
+        assert_eq!(result.output, "started\n");
+        assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+        sleep(Duration::from_millis(100)).await;
+        assert!(!running(&sleeper), "the grandchild outlived the run");
+    }
+}
@@ -1,13 +1,29 @@
+use crate::cache::{remove_old_files, GcReport};
+use crate::compiler::{command_line, spawn_failure, toolchain_label, CompileTimeout};
+use crate::diagnostics::truncate_lines;
+use crate::executor::{idle_watchdog, kill_process_group, read_cpu_time_ms, set_environment, DEFAULT_PROCESS_LIMIT, IDLENESS_LIMIT_EXCEEDED, TIME_LIMIT_EXCEEDED};
+#[cfg(unix)]
+use crate::executor::{limit_address_space, limit_processes};
+#[cfg(windows)]
+use crate::executor::Job;
+#[cfg(target_os = "linux")]
+use crate::executor::wait_for_exit_without_reaping;
+use crate::sandbox::Sandbox;
+use crate::types::{relative_source_path, CodeFile, Language};
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration as StdDuration, SystemTime, UNIX_EPOCH};
 use tempfile::TempDir;
 use tokio::fs as tokio_fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command as TokioCommand;
-use tokio::time::{timeout, Duration};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
 
 static RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -21,33 +37,21 @@ fn next_run_path() -> PathBuf {
     std::env::temp_dir().join(format!("dsa-run-{}-{}{}", ts, count, suffix))
 }
 
-fn clean_old_run_artifacts() {
-    let tmp = std::env::temp_dir();
-    let cutoff = SystemTime::now()
-        .checked_sub(StdDuration::from_secs(60 * 30)) // ~30 minutes
-        .unwrap_or(SystemTime::now());
-    if let Ok(entries) = std::fs::read_dir(tmp) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                if name.starts_with("dsa-run-") {
-                    if let Ok(meta) = entry.metadata() {
-                        if let Ok(modified) = meta.modified() {
-                            if modified < cutoff {
-                                let _ = std::fs::remove_file(&path);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+/// Default for how long a binary built by `compile_files` is kept around for `Session`/`execute`
+pub const RUN_ARTIFACT_MAX_AGE: StdDuration = StdDuration::from_secs(60 * 30);
+
+/// `DSA_JUDGE_RUN_ARTIFACT_MAX_AGE_SECS`, or `RUN_ARTIFACT_MAX_AGE` when unset or invalid
+pub fn run_artifact_max_age() -> StdDuration {
+    std::env::var("DSA_JUDGE_RUN_ARTIFACT_MAX_AGE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map_or(RUN_ARTIFACT_MAX_AGE, StdDuration::from_secs)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodeFile {
-    pub filename: String,
-    pub content: String,
+/// Remove `dsa-run-*` binaries in `dir` (where `compile_files` puts them: the temp dir)
+/// older than `max_age`
+pub(crate) fn clean_old_run_artifacts(dir: &Path, max_age: StdDuration) -> GcReport {
+    remove_old_files(dir, "dsa-run-", max_age)
 }
 
 #[derive(Debug, Serialize)]
@@ -56,16 +60,53 @@ pub struct CompileResult {
     pub executable_path: Option<String>,
     pub error: Option<String>,
     pub compile_time_ms: u64,
+    /// Always false: project builds bypass the compile cache and run the compiler every time
+    pub compilation_cached: bool,
+    /// The compiler command line that was run
+    pub command: String,
+    /// Name and version of the compiler, when the build failed
+    pub toolchain: Option<String>,
+    /// Line count of `error` before `truncate_error` cut it
+    pub error_lines: Option<usize>,
 }
 
+impl CompileResult {
+    /// Keep only the first `max_lines` lines of `error`, recording the full length
+    pub fn truncate_error(&mut self, max_lines: usize) {
+        if let Some((error, total)) = self.error.as_deref().and_then(|e| truncate_lines(e, max_lines)) {
+            self.error = Some(error);
+            self.error_lines = Some(total);
+        }
+    }
+}
+
+/// Default limit for `compile_files`; project builds get more room than single files
+pub(crate) const PROJECT_COMPILE_TIMEOUT: StdDuration = StdDuration::from_secs(15);
+
 /// Compile multiple files (C or C++) for interactive execution
-pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<CompileResult> {
+pub async fn compile_files(files: Vec<CodeFile>, language: Language) -> Result<CompileResult> {
+    compile_files_with_timeout(files, language, PROJECT_COMPILE_TIMEOUT).await
+}
+
+/// `compile_files` with an explicit compiler time limit
+pub async fn compile_files_with_timeout(files: Vec<CodeFile>, language: Language, compile_timeout: StdDuration) -> Result<CompileResult> {
+    compile_files_retaining(files, language, compile_timeout, run_artifact_max_age()).await
+}
+
+/// `compile_files_with_timeout`, first removing run binaries older than `max_age`
+/// instead of `run_artifact_max_age()`
+pub async fn compile_files_retaining(
+    files: Vec<CodeFile>,
+    language: Language,
+    compile_timeout: StdDuration,
+    max_age: StdDuration,
+) -> Result<CompileResult> {
     let start = std::time::Instant::now();
     let temp_dir = TempDir::new().context("Failed to create temp directory")?;
     
     // Write all files to temp directory
     for file in &files {
-        let file_path = temp_dir.path().join(&file.filename);
+        let file_path = temp_dir.path().join(relative_source_path(&file.filename)?);
         
         // Create parent directories if needed
         if let Some(parent) = file_path.parent() {
@@ -78,9 +119,9 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
     
     // Determine compiler and source files
     let compiler = match language {
-        "c" => "gcc",
-        "cpp" => "g++",
-        "rust" => "rustc",
+        Language::C => "gcc",
+        Language::Cpp => "g++",
+        Language::Rust => "rustc",
         _ => return Err(anyhow::anyhow!("Unsupported language: {}", language)),
     };
     
@@ -89,7 +130,7 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
         .filter(|f| {
             let fname = f.filename.to_lowercase();
             match language {
-                "rust" => fname.ends_with(".rs"),
+                Language::Rust => fname.ends_with(".rs"),
                 _ => fname.ends_with(".c") || fname.ends_with(".cpp"),
             }
         })
@@ -106,9 +147,10 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
     let mut cmd = TokioCommand::new(compiler);
     cmd.current_dir(temp_dir.path())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
     
-    if language == "rust" {
+    if language == Language::Rust {
         for source in &source_files {
             cmd.arg(source.file_name().unwrap());
         }
@@ -122,7 +164,7 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
         // Add output and flags
         cmd.arg("-o").arg(&executable_path);
         
-        if language == "c" {
+        if language == Language::C {
             cmd.arg("-std=c99");
         } else {
             cmd.arg("-std=c++17");
@@ -131,13 +173,18 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
         cmd.arg("-O2")
             .arg("-Wall")
             .arg("-Wextra");
+
+        if language == Language::C {
+            cmd.arg("-lm");
+        }
     }
     
     // Execute compilation with timeout
-    let output = timeout(Duration::from_secs(15), cmd.output())
+    let command = command_line(&cmd);
+    let output = timeout(compile_timeout, cmd.output())
         .await
-        .context("Compilation timeout")?
-        .context("Failed to execute compiler")?;
+        .map_err(|_| CompileTimeout { compiler: compiler.to_string(), limit: compile_timeout })?
+        .map_err(|e| spawn_failure(compiler, e))?;
     
     let compile_time_ms = start.elapsed().as_millis() as u64;
     
@@ -148,11 +195,15 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
             executable_path: None,
             error: Some(error),
             compile_time_ms,
+            compilation_cached: false,
+            command,
+            toolchain: Some(toolchain_label(compiler).await),
+            error_lines: None,
         });
     }
     
     // Move executable to a stable temp path and cleanup build dir
-    clean_old_run_artifacts();
+    clean_old_run_artifacts(&std::env::temp_dir(), max_age);
     let final_path = next_run_path();
     if let Some(parent) = final_path.parent() {
         let _ = std::fs::create_dir_all(parent);
@@ -165,12 +216,288 @@ pub async fn compile_files(files: Vec<CodeFile>, language: &str) -> Result<Compi
         executable_path: Some(final_path.to_string_lossy().to_string()),
         error: None,
         compile_time_ms,
+        compilation_cached: false,
+        command,
+        toolchain: None,
+        error_lines: None,
     })
 }
 
-#[derive(Debug, Serialize)]
+/// Timing and memory telemetry of a session's run
+#[derive(Debug, Clone, Copy, Default, Serialize)]
 pub struct ExecutionMetrics {
     pub execution_time_ms: u64,
+    /// 0 where the platform doesn't report it
     pub peak_memory_kb: u64,
 }
 
+/// Output of a running session, streamed to the client as it arrives
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "stream", rename_all = "lowercase")]
+pub enum SessionEvent {
+    Stdout { data: String },
+    Stderr { data: String },
+    /// The program ended; sent after all of its output. `error` is set when it was killed
+    /// for hitting `SESSION_WALL_LIMIT` or `SESSION_IDLE_LIMIT`
+    Exit {
+        code: Option<i32>,
+        metrics: ExecutionMetrics,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<&'static str>,
+    },
+}
+
+/// Longest a session's program may run
+pub const SESSION_WALL_LIMIT: StdDuration = StdDuration::from_secs(30 * 60);
+
+/// Most memory a session's program may reserve (512 MB): its address space on Unix, the
+/// job's committed memory on Windows
+pub const SESSION_MEMORY_LIMIT_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Longest a session's program may go without input, output or CPU use (Linux only)
+pub const SESSION_IDLE_LIMIT: StdDuration = StdDuration::from_secs(5 * 60);
+
+/// Whether `path` is a binary `compile_files` put in the temp dir (`dsa-run-*`), the
+/// only programs a session may start
+fn is_run_artifact(path: &Path) -> bool {
+    let named = path.file_name().and_then(|n| n.to_str()).is_some_and(|name| name.starts_with("dsa-run-"));
+    let parent = path.parent().and_then(|dir| dir.canonicalize().ok());
+    named && parent.is_some_and(|dir| std::env::temp_dir().canonicalize().is_ok_and(|temp| dir == temp))
+}
+
+/// A `dsa-run-*` file in `dir`, last modified `age` ago
+#[cfg(test)]
+pub(crate) fn run_artifact_aged(dir: &Path, age: StdDuration) -> PathBuf {
+    let path = dir.join(next_run_path().file_name().unwrap());
+    std::fs::write(&path, b"binary").unwrap();
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() - age).unwrap();
+    path
+}
+
+/// A compiled program run for a terminal-style view: the client feeds stdin piece by
+/// piece and gets stdout/stderr back as `SessionEvent`s. Dropping it kills the program.
+pub struct Session {
+    stdin: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    driver: JoinHandle<()>,
+    pid: Option<u32>,
+    /// Set once the program is reaped and all its output sent, just before `SessionEvent::Exit`
+    exited: Arc<AtomicBool>,
+}
+
+/// Forward everything read from `stream` to `on_event` as it arrives, counting the bytes
+async fn pump(
+    stream: Option<impl AsyncRead + Unpin>,
+    on_event: &(dyn Fn(SessionEvent) + Send + Sync),
+    wrap: fn(String) -> SessionEvent,
+    transferred: &AtomicU64,
+) {
+    let Some(mut stream) = stream else { return };
+    let mut buf = vec![0u8; 8192];
+    while let Ok(n @ 1..) = stream.read(&mut buf).await {
+        transferred.fetch_add(n as u64, Ordering::Relaxed);
+        on_event(wrap(String::from_utf8_lossy(&buf[..n]).to_string()));
+    }
+}
+
+impl Session {
+    /// Start `executable_path` (as returned by `compile_files`) in the sandbox. The program
+    /// is killed after `SESSION_WALL_LIMIT`, or `SESSION_IDLE_LIMIT` without activity, and
+    /// can't reserve more than `SESSION_MEMORY_LIMIT_BYTES`.
+    pub fn start(executable_path: &str, on_event: impl Fn(SessionEvent) + Send + Sync + 'static) -> Result<Self> {
+        if !is_run_artifact(Path::new(executable_path)) {
+            anyhow::bail!("Not a compiled program: {}; pass the executable_path returned by execute", executable_path);
+        }
+        let mut cmd = TokioCommand::new(executable_path);
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        set_environment(&mut cmd, &[]);
+        #[cfg(unix)]
+        cmd.process_group(0);
+        #[cfg(unix)]
+        limit_address_space(&mut cmd, SESSION_MEMORY_LIMIT_BYTES);
+        #[cfg(unix)]
+        limit_processes(&mut cmd, DEFAULT_PROCESS_LIMIT);
+        Sandbox::confine(&mut cmd, false, &[]);
+        #[cfg(windows)]
+        Job::suspend_on_spawn(&mut cmd);
+        let mut child = cmd.spawn().context("Failed to start process")?;
+        // Moved into the driver, so whatever the program left running dies with the session
+        #[cfg(windows)]
+        let job = {
+            let job = Job::new(Some(SESSION_MEMORY_LIMIT_BYTES)).context("Failed to create job object")?;
+            job.assign(&child).context("Failed to assign process to job object")?;
+            job
+        };
+        let started = std::time::Instant::now();
+        let pid = child.id();
+
+        let (stdin_tx, mut stdin_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        let stdin = child.stdin.take();
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        let exited = Arc::new(AtomicBool::new(false));
+        let exited_flag = Arc::clone(&exited);
+        let driver = tokio::spawn(async move {
+            // Bytes in either direction, watched by the idle limit
+            let transferred = Arc::new(AtomicU64::new(0));
+            let written = Arc::clone(&transferred);
+            // Closing the channel drops stdin, so the program sees EOF
+            let writer = tokio::spawn(async move {
+                let Some(mut pipe) = stdin else { return };
+                while let Some(data) = stdin_rx.recv().await {
+                    if pipe.write_all(&data).await.is_err() || pipe.flush().await.is_err() {
+                        break;
+                    }
+                    written.fetch_add(data.len() as u64, Ordering::Relaxed);
+                }
+            });
+            let on_event: &(dyn Fn(SessionEvent) + Send + Sync) = &on_event;
+            let exit = async {
+                // Peak RSS is only readable while the exited program is still unreaped
+                #[cfg(target_os = "linux")]
+                let peak_memory_kb = match pid {
+                    Some(pid) => {
+                        let peak = tokio::task::spawn_blocking(move || wait_for_exit_without_reaping(pid)).await.ok().flatten();
+                        // The unreaped leader pins the group id, so this can't hit a recycled group
+                        kill_process_group(pid);
+                        peak
+                    }
+                    None => None,
+                };
+                #[cfg(not(target_os = "linux"))]
+                let peak_memory_kb = None;
+                let status = child.wait().await;
+                let metrics = ExecutionMetrics {
+                    execution_time_ms: started.elapsed().as_millis() as u64,
+                    peak_memory_kb: peak_memory_kb.unwrap_or(0),
+                };
+                (status, metrics)
+            };
+            // Killing the group ends the program, after which `exit` resolves as usual
+            let mut error = None;
+            let limits = async {
+                error = tokio::select! {
+                    _ = tokio::time::sleep(SESSION_WALL_LIMIT) => Some(TIME_LIMIT_EXCEEDED),
+                    _ = idle_watchdog(Some(SESSION_IDLE_LIMIT), || {
+                        (transferred.load(Ordering::Relaxed), pid.and_then(read_cpu_time_ms))
+                    }) => Some(IDLENESS_LIMIT_EXCEEDED),
+                };
+                if let Some(pid) = pid {
+                    kill_process_group(pid);
+                }
+            };
+            let run = async {
+                tokio::join!(
+                    pump(stdout, on_event, |data| SessionEvent::Stdout { data }, &transferred),
+                    pump(stderr, on_event, |data| SessionEvent::Stderr { data }, &transferred),
+                    exit,
+                )
+                .2
+            };
+            tokio::pin!(run);
+            let (status, metrics) = tokio::select! {
+                finished = &mut run => finished,
+                _ = limits => run.await,
+            };
+            writer.abort();
+            #[cfg(windows)]
+            drop(job);
+            exited_flag.store(true, Ordering::Relaxed);
+            on_event(SessionEvent::Exit { code: status.ok().and_then(|s| s.code()), metrics, error });
+        });
+        Ok(Self { stdin: Some(stdin_tx), driver, pid, exited })
+    }
+
+    /// Whether the program has ended (its `SessionEvent::Exit` is sent right after)
+    pub fn has_exited(&self) -> bool {
+        self.exited.load(Ordering::Relaxed)
+    }
+
+    /// Queue bytes for the program's stdin
+    pub fn write(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        let stdin = self.stdin.as_ref().context("Session stdin is closed")?;
+        stdin.send(data.into()).map_err(|_| anyhow::anyhow!("Session has exited"))
+    }
+
+    /// Close the program's stdin (end of input) once queued writes are flushed
+    pub fn close_stdin(&mut self) {
+        self.stdin = None;
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Once reaped, the group id may belong to someone else
+        if let (Some(pid), false) = (self.pid, self.has_exited()) {
+            kill_process_group(pid);
+        }
+        self.driver.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_old_run_artifacts_keeps_recent_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = run_artifact_aged(dir.path(), StdDuration::from_secs(2 * 60 * 60));
+        let fresh = run_artifact_aged(dir.path(), StdDuration::ZERO);
+
+        let report = clean_old_run_artifacts(dir.path(), StdDuration::from_secs(60 * 60));
+        assert_eq!(report.removed_files, 1);
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+    }
+
+    #[tokio::test]
+    async fn sessions_only_start_run_artifacts() {
+        for path in ["/bin/sh", "dsa-run-1", "/usr/bin/dsa-run-1"] {
+            assert!(Session::start(path, |_| {}).is_err(), "{} was started", path);
+        }
+        assert!(is_run_artifact(&next_run_path()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sessions_have_a_memory_cap() {
+        let code = "#include <stdio.h>\n#include <stdlib.h>\nint main(void) { puts(malloc(1u << 30) ? \"granted\" : \"refused\"); return 0; }";
+        let files = vec![CodeFile { filename: "main.c".to_string(), content: code.to_string() }];
+        let compiled = compile_files(files, Language::C).await.unwrap();
+        let path = compiled.executable_path.expect("compiles");
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let _session = Session::start(&path, move |event| {
+            if let SessionEvent::Stdout { data } = event {
+                let _ = tx.send(data);
+            }
+        })
+        .unwrap();
+        let output = rx.recv().await.unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(output.trim(), "refused");
+    }
+
+    #[test]
+    fn relative_source_path_rejects_escapes() {
+        for name in ["../escape.c", "src/../../escape.c", "/etc/passwd.c", "", ".", "./"] {
+            assert!(relative_source_path(name).is_err(), "{:?} was accepted", name);
+        }
+        assert_eq!(relative_source_path("src/./util.c").unwrap(), Path::new("src/./util.c"));
+    }
+
+    #[tokio::test]
+    async fn compile_files_refuses_to_write_outside_the_build() {
+        let files = vec![
+            CodeFile { filename: "main.c".to_string(), content: "int main(void) { return 0; }".to_string() },
+            CodeFile { filename: "../escape.c".to_string(), content: "int x;".to_string() },
+        ];
+        let escaped = std::env::temp_dir().join("escape.c");
+        let existed = escaped.exists();
+        let failed = compile_files(files, Language::C).await.map_or(true, |result| !result.success);
+        assert!(failed);
+        assert_eq!(escaped.exists(), existed);
+    }
+}
@@ -0,0 +1,235 @@
+//! Interactive problem support.
+//!
+//! The interactor and the contestant program run side by side with their standard
+//! streams cross-connected: interactor stdout feeds solution stdin and solution
+//! stdout feeds interactor stdin. The interactor decides the verdict:
+//!
+//! * argv: `interactor <input-file> <expected-answer-file> <score-file>`
+//! * exit code `0` accepts, `1` and `2` reject; any other exit code or a crash
+//!   means the interactor itself failed
+//! * a score in `0.0..=1.0` may be written to the score file; as with checkers it only
+//!   counts on exit code `0`
+//! * stderr is treated as a human-readable comment and passed back to the caller
+
+use crate::cache::CachePin;
+use crate::checker::CheckerOutcome;
+use crate::compiler::{Artifact, CompileOptions, Compiler};
+use crate::executor::{describe_exit, idle_watchdog, kill_process_group, read_cpu_time_ms, set_environment, MemorySampler, ProgramInput, DEFAULT_OUTPUT_LIMIT_BYTES, DEFAULT_SAMPLING_INTERVAL, IDLENESS_LIMIT_EXCEEDED, TIME_LIMIT_EXCEEDED};
+use crate::types::CodeFile;
+use crate::sandbox::Sandbox;
+use crate::types::ExecutionResult;
+use anyhow::{Context, Result};
+use std::process::Stdio;
+use std::time::Instant;
+use tempfile::TempDir;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::{timeout, Duration};
+
+/// Result of running the solution against the interactor on one test case
+pub struct Interaction {
+    /// The solution's side; its output is consumed by the interactor, so `output` stays empty
+    pub execution: ExecutionResult,
+    /// The interactor's verdict, or why the interactor itself failed
+    pub outcome: Result<CheckerOutcome>,
+}
+
+/// Limits and launch settings applied to the solution side of an interaction
+pub struct SolutionLimits {
+    /// Wall-clock budget for the solution/interactor pair
+    pub time_limit_ms: u64,
+    pub address_space_limit_bytes: Option<u64>,
+    pub process_limit: Option<u64>,
+    /// Give up once neither side has used CPU for this long (a deadlock)
+    pub idle_limit_ms: Option<u64>,
+    pub allow_network: bool,
+    /// Command-line arguments for the solution
+    pub args: Vec<String>,
+    /// The solution's whole environment, apart from `PATH`
+    pub env: Vec<(String, String)>,
+}
+
+/// A compiled interactor, reused across all test cases of a submission
+pub struct Interactor {
+    artifact: Artifact,
+    _pin: CachePin,
+}
+
+/// Read a captured stderr stream, bounded like regular program output
+fn read_capped(stream: Option<impl AsyncRead + Unpin + Send + 'static>) -> tokio::task::JoinHandle<String> {
+    tokio::spawn(async move {
+        let mut buf = Vec::new();
+        if let Some(s) = stream {
+            let _ = s.take(DEFAULT_OUTPUT_LIMIT_BYTES).read_to_end(&mut buf).await;
+        }
+        String::from_utf8_lossy(&buf).to_string()
+    })
+}
+
+impl Interactor {
+    /// Compile the interactor as C when the file ends in `.c`, C++ otherwise
+    pub async fn compile(compiler: &Compiler, source: &CodeFile) -> Result<Self> {
+        let options = CompileOptions::default();
+        let build = if source.filename.to_lowercase().ends_with(".c") {
+            compiler.compile_c(&source.content, &options, &[]).await
+        } else {
+            compiler.compile_cpp(&source.content, &options, &[]).await
+        };
+        let artifact = build.context("Failed to compile interactor")?.artifact;
+        Ok(Self { _pin: compiler.pin(&artifact), artifact })
+    }
+
+    /// Run `solution` against the interactor; the time limit covers the pair
+    pub async fn run(&self, solution: &Artifact, input: &ProgramInput<'_>, answer: &str, limits: &SolutionLimits) -> Result<Interaction> {
+        let dir = TempDir::new().context("Failed to create interactor directory")?;
+        let input_path = input.to_path(dir.path().join("input.txt")).await?;
+        let answer_path = dir.path().join("answer.txt");
+        let score_path = dir.path().join("score.txt");
+        tokio::fs::write(&answer_path, answer).await?;
+
+        let start_time = Instant::now();
+        let mut solution_cmd = solution.command();
+        solution_cmd
+            .args(&limits.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        set_environment(&mut solution_cmd, &limits.env);
+        #[cfg(unix)]
+        solution_cmd.process_group(0);
+        #[cfg(unix)]
+        if let Some(bytes) = limits.address_space_limit_bytes {
+            crate::executor::limit_address_space(&mut solution_cmd, bytes);
+        }
+        #[cfg(unix)]
+        if let Some(max) = limits.process_limit {
+            crate::executor::limit_processes(&mut solution_cmd, max);
+        }
+        Sandbox::confine(&mut solution_cmd, limits.allow_network, solution.run_files().as_slice());
+        #[cfg(windows)]
+        crate::executor::Job::suspend_on_spawn(&mut solution_cmd);
+        let mut solution_child = solution_cmd.spawn().context("Failed to start process")?;
+        #[cfg(windows)]
+        let _job = {
+            let job = crate::executor::Job::new(limits.address_space_limit_bytes).context("Failed to create job object")?;
+            job.assign(&solution_child).context("Failed to assign process to job object")?;
+            job
+        };
+
+        let to_solution: Stdio = solution_child.stdin.take().context("Missing solution stdin")?.try_into()?;
+        let from_solution: Stdio = solution_child.stdout.take().context("Missing solution stdout")?.try_into()?;
+        // The command owns our copies of the pipe ends; it is dropped right after
+        // spawning so each side sees EOF as soon as the other exits
+        let mut interactor_child = {
+            let mut cmd = self.artifact.command();
+            cmd.arg(&input_path)
+                .arg(&answer_path)
+                .arg(&score_path)
+                .stdin(from_solution)
+                .stdout(to_solution)
+                .stderr(Stdio::piped())
+                .kill_on_drop(true);
+            cmd.spawn().context("Failed to start interactor")?
+        };
+
+        let solution_stderr = read_capped(solution_child.stderr.take());
+        let interactor_stderr = read_capped(interactor_child.stderr.take());
+
+        let (solution_pid, interactor_pid) = (solution_child.id(), interactor_child.id());
+        let sampler = MemorySampler::start(solution_pid, Some(DEFAULT_SAMPLING_INTERVAL));
+        let mut cpu_time_ms = None;
+        // Like `Executor`: the solution's peak and CPU time are read while it is still a zombie,
+        // and whatever it left running in the background is killed with its group
+        let solution_exit = async {
+            #[cfg(target_os = "linux")]
+            if let Some(pid) = solution_pid {
+                if let Ok(Some(kb)) = tokio::task::spawn_blocking(move || crate::executor::wait_for_exit_without_reaping(pid)).await {
+                    sampler.record(kb);
+                }
+                // The unreaped leader pins the group id, so this can't hit a recycled group
+                kill_process_group(pid);
+            }
+            cpu_time_ms = solution_pid.and_then(read_cpu_time_ms);
+            let status = solution_child.wait().await;
+            #[cfg(not(target_os = "linux"))]
+            if let Some(pid) = solution_pid {
+                kill_process_group(pid);
+            }
+            status
+        };
+        let waited = timeout(Duration::from_millis(limits.time_limit_ms), async {
+            tokio::select! {
+                statuses = async { tokio::join!(solution_exit, interactor_child.wait()) } => Some(statuses),
+                _ = idle_watchdog(limits.idle_limit_ms.map(Duration::from_millis), || {
+                    (solution_pid.and_then(read_cpu_time_ms), interactor_pid.and_then(read_cpu_time_ms))
+                }) => None,
+            }
+        })
+        .await;
+        let execution_time = start_time.elapsed().as_millis() as u64;
+
+        let (solution_status, interactor_status) = match waited {
+            Ok(Some((solution_status, interactor_status))) => (solution_status?, interactor_status?),
+            stopped => {
+                let (error, reason) = match stopped {
+                    Ok(_) => (IDLENESS_LIMIT_EXCEEDED, "Interaction stalled"),
+                    Err(_) => (TIME_LIMIT_EXCEEDED, "Interaction timed out"),
+                };
+                if let Some(pid) = solution_pid {
+                    kill_process_group(pid);
+                }
+                let _ = solution_child.kill().await;
+                let _ = interactor_child.kill().await;
+                let memory_usage = sampler.finish().await;
+                return Ok(Interaction {
+                    execution: ExecutionResult {
+                        success: false,
+                        output: String::new(),
+                        output_bytes: Vec::new(),
+                        error: Some(error.to_string()),
+                        stderr: String::new(),
+                        execution_time,
+                        cpu_time_ms: None,
+                        memory_usage,
+                    },
+                    outcome: Err(anyhow::anyhow!(reason)),
+                });
+            }
+        };
+
+        let memory_usage = sampler.finish().await;
+        let solution_stderr = solution_stderr.await.unwrap_or_default();
+        let message = interactor_stderr.await.unwrap_or_default().trim().to_string();
+
+        let outcome = match interactor_status.code() {
+            Some(code @ 0..=2) => {
+                let score = tokio::fs::read_to_string(&score_path)
+                    .await
+                    .ok()
+                    .and_then(|s| s.split_whitespace().next().and_then(|tok| tok.parse::<f64>().ok()))
+                    .filter(|s| (0.0..=1.0).contains(s));
+                Ok(CheckerOutcome {
+                    accepted: code == 0,
+                    score,
+                    message: if message.is_empty() { None } else { Some(message) },
+                })
+            }
+            code => Err(anyhow::anyhow!("Interactor failed (exit code {:?}): {}", code, message)),
+        };
+
+        let success = solution_status.success();
+        Ok(Interaction {
+            execution: ExecutionResult {
+                success,
+                output: String::new(),
+                output_bytes: Vec::new(),
+                error: (!success).then(|| describe_exit(&solution_status)),
+                stderr: solution_stderr,
+                execution_time,
+                cpu_time_ms,
+                memory_usage,
+            },
+            outcome,
+        })
+    }
+}
@@ -1,12 +1,188 @@
 use crate::types::*;
-use crate::compiler::Compiler;
-use crate::executor::Executor;
+use crate::cache::{CacheStats, CacheUsage, CompileCache, GcReport};
+use crate::checker::Checker;
+use crate::compiler::{clean_old_debug_binaries, Artifact, Build, CompileFailure, CompileOptions, CompileResourceLimit, CompileTimeout, Compiler, CompilerNotFound, Sanitizer, Toolchain, UnsupportedStandard};
+use crate::diagnostics;
+use crate::comparator::{self, Comparator, Comparison, NormalizedComparator};
+use crate::interactive::{self, clean_old_run_artifacts, compile_files_retaining, run_artifact_max_age, PROJECT_COMPILE_TIMEOUT};
+use crate::interactor::{Interactor, SolutionLimits};
+use crate::executor::{Executor, ProgramInput, DEFAULT_OUTPUT_LIMIT_BYTES, DEFAULT_PROCESS_LIMIT, IDLENESS_LIMIT_EXCEEDED, MEMORY_LIMIT_EXCEEDED, OUTPUT_LIMIT_EXCEEDED, SEED_ENV_VAR, TIME_LIMIT_EXCEEDED, UBSAN_MARKER, UNDEFINED_BEHAVIOR};
 use crate::sandbox::Sandbox;
 use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use futures::stream::{self, StreamExt};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Lines kept in a wrong-answer diff unless the request says otherwise
+const DEFAULT_MAX_DIFF_LINES: usize = 200;
+
+/// Largest a `Gzip`-encoded input or output may decompress to (256 MB), so a small
+/// compression bomb can't exhaust the judge's memory
+const MAX_DECODED_TEST_DATA_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Replaces a hidden test case's stderr (or other error detail) in responses
+const HIDDEN_DETAILS: &str = "Details hidden";
+
+/// Everything a single test case run needs from the enclosing judge call
+struct TestRun<'a> {
+    request: &'a JudgeRequest,
+    artifact: &'a Artifact,
+    checker: Option<&'a Checker>,
+    interactor: Option<&'a Interactor>,
+    /// Stdin of each test case, by index
+    inputs: &'a [ProgramInput<'a>],
+    /// Compiled `expected_regex` of each test case, by index
+    patterns: &'a [Option<Regex>],
+    /// Raw expected and accepted outputs of each encoded test case for `binary_output`, by index
+    expected_bytes: &'a [Option<Vec<Vec<u8>>>],
+    /// Decides correctness for runs that exited cleanly
+    comparator: &'a dyn Comparator,
+    address_space_limit: bool,
+    process_limit: Option<u64>,
+    /// Set when a run couldn't be started at all
+    spawn_failed: AtomicBool,
+    /// Time spent comparing outputs, summed over the cases, in microseconds
+    comparison_time_us: AtomicU64,
+}
+
+/// Runtime behind `Judge::judge_blocking`, built on first use and shared afterwards
+fn blocking_runtime() -> Result<&'static tokio::runtime::Runtime> {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start judge runtime")?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// JVM heap for `Judge::compile`, which has no problem memory limit to size it from
+const STANDALONE_JAVA_HEAP_MB: u64 = 256;
+
+/// A successful `Judge::compile`
+pub struct CompileOutcome {
+    /// Native executable (cached) or interpreter command line
+    pub artifact: Artifact,
+    pub warnings: Option<String>,
+    /// The compiler command line that was run
+    pub command: String,
+    pub compile_time_ms: u64,
+    pub executable_size_bytes: Option<u64>,
+    /// Served from the compile cache without running the compiler
+    pub cached: bool,
+    /// On cache hits, how long the original build took (if recorded)
+    pub cached_build_time_ms: Option<u64>,
+    /// Holds scripts and class files, which live in the compiler's directory; dropping
+    /// the outcome removes them (cached native executables stay)
+    _workspace: Compiler,
+}
+
+/// How long `Judge::gc` keeps files that haven't been used
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// `dsa-run-*` binaries in the temp dir (default `DSA_JUDGE_RUN_ARTIFACT_MAX_AGE_SECS`,
+    /// else 30 minutes); also applied when a project build stores a new one
+    pub run_artifacts: Duration,
+    /// Compile cache entries, by last use (default 7 days)
+    pub cache_entries: Duration,
+    /// `debug_build` binaries in `debug_binary_dir`, by last use (default 1 day)
+    pub debug_binaries: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            run_artifacts: run_artifact_max_age(),
+            cache_entries: Duration::from_secs(7 * 24 * 60 * 60),
+            debug_binaries: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Operational counters reported by the `stats` action
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct JudgeStats {
+    pub cache: CacheUsage,
+    /// Judge calls currently running
+    pub in_flight: u64,
+    /// Judge calls finished since startup
+    pub judged_total: u64,
+}
+
+/// Result of `Judge::warm_cache`
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct WarmReport {
+    /// Compiled now and added to the cache
+    pub built: usize,
+    pub already_cached: usize,
+    /// Entries that didn't compile
+    pub failed: usize,
+    /// Python and Java entries, whose builds aren't cached
+    pub skipped: usize,
+}
+
+/// A test case whose stored answer the reference solution doesn't reproduce
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TestDataMismatch {
+    pub test_case_id: usize,
+    /// The reference's verdict, e.g. `WrongAnswer` or `TimeLimitExceeded`
+    pub verdict: TestVerdict,
+    pub expected_output: String,
+    pub actual_output: String,
+    pub diff: Option<String>,
+    /// The reference's stderr or failure reason, when it didn't exit cleanly
+    pub error: Option<String>,
+}
+
+/// Result of `Judge::validate_testdata`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TestDataReport {
+    pub total_test_cases: usize,
+    pub mismatches: Vec<TestDataMismatch>,
+}
+
+impl TestDataReport {
+    /// Whether the reference solution reproduced every stored answer
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Counts a judge call as in flight until dropped, including when it is cancelled
+struct InFlightGuard<'a>(&'a AtomicU64);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 /// Main judge engine that orchestrates compilation, execution, and evaluation
 pub struct Judge {
     _sandbox: Sandbox,
+    cache: CompileCache,
+    /// Replaces normalized comparison for problems without a custom checker
+    comparator: Option<Arc<dyn Comparator>>,
+    /// Directory that file-based test inputs must live under
+    test_data_root: Option<PathBuf>,
+    /// CPU core every run is pinned to
+    cpu_core: Option<usize>,
+    /// Run binary and cache entry ages for project builds and periodic `gc` calls
+    retention: RetentionPolicy,
+    /// Where `gc` looks for run binaries: the temp dir, where `compile_files` writes them
+    run_artifact_dir: PathBuf,
+    in_flight: AtomicU64,
+    judged_total: AtomicU64,
 }
 
 impl Judge {
@@ -14,124 +190,1169 @@ impl Judge {
         let sandbox = Sandbox::new().context("Failed to create sandbox")?;
         sandbox.setup().context("Failed to setup sandbox")?;
         
-        Ok(Self { _sandbox: sandbox })
+        Ok(Self {
+            _sandbox: sandbox,
+            cache: CompileCache::default(),
+            comparator: None,
+            test_data_root: std::env::var_os("DSA_JUDGE_TEST_DATA_ROOT").map(PathBuf::from),
+            cpu_core: std::env::var("DSA_JUDGE_CPU_CORE").ok().and_then(|v| v.parse().ok()),
+            retention: RetentionPolicy::default(),
+            run_artifact_dir: std::env::temp_dir(),
+            in_flight: AtomicU64::new(0),
+            judged_total: AtomicU64::new(0),
+        })
+    }
+
+    /// Remove run binaries, debug binaries and cache entries unused for longer than
+    /// `policy` allows, then trim the cache to its size cap
+    pub fn gc(&self, policy: &RetentionPolicy) -> GcReport {
+        clean_old_run_artifacts(&self.run_artifact_dir, policy.run_artifacts)
+            .merge(clean_old_debug_binaries(policy.debug_binaries))
+            .merge(self.cache.remove_stale(policy.cache_entries))
+    }
+
+    /// Have `gc` sweep run binaries in `dir` instead of the temp dir
+    pub fn with_run_artifact_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.run_artifact_dir = dir.into();
+        self
+    }
+
+    /// Keep run binaries and cache entries for these ages; defaults to `RetentionPolicy::default()`
+    pub fn with_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = policy;
+        self
+    }
+
+    /// The configured retention, for periodic `gc` calls
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// `interactive::compile_files` for `Session`/`execute`, dropping run binaries
+    /// older than this judge's retention instead of the environment default
+    pub async fn compile_files(&self, files: Vec<CodeFile>, language: Language) -> Result<interactive::CompileResult> {
+        compile_files_retaining(files, language, PROJECT_COMPILE_TIMEOUT, self.retention.run_artifacts).await
+    }
+
+    /// Cache usage and judge counters since startup
+    pub fn stats(&self) -> JudgeStats {
+        JudgeStats {
+            cache: self.cache.usage(),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            judged_total: self.judged_total.load(Ordering::Relaxed),
+        }
+    }
+
+    /// What the compile cache holds: entries, bytes and the oldest/newest last use
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Delete every compile cache entry not in use, returning how many were removed
+    pub fn clear_cache(&self) -> Result<usize> {
+        self.cache
+            .clear()
+            .with_context(|| format!("Failed to clear compile cache {}", self.cache.dir().display()))
+    }
+
+    /// Use a specific compile cache (location and size cap). By default the cache
+    /// lives in `DSA_JUDGE_CACHE_DIR`, read once here, or the platform cache dir
+    pub fn with_cache(mut self, cache: CompileCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Decide correctness with `comparator` instead of the request's normalization options.
+    /// A problem's custom checker still takes precedence
+    pub fn with_comparator(mut self, comparator: impl Comparator + 'static) -> Self {
+        self.comparator = Some(Arc::new(comparator));
+        self
+    }
+
+    /// Pin every run to CPU core `core` (Linux; defaults to `DSA_JUDGE_CPU_CORE`) so timings
+    /// don't swing with core migrations. Parallel test cases then share that core
+    pub fn with_cpu_affinity(mut self, core: usize) -> Self {
+        self.cpu_core = Some(core);
+        self
+    }
+
+    /// Allow test inputs given as files under `root` (defaults to `DSA_JUDGE_TEST_DATA_ROOT`)
+    pub fn with_test_data_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.test_data_root = Some(root.into());
+        self
+    }
+
+    /// Compile `code` without running anything, e.g. for an editor's build button.
+    /// Failures are `CompileFailure`/`CompileTimeout`/`CompileResourceLimit`/`CompilerNotFound`/
+    /// `UnsupportedStandard` errors, as in `judge`
+    pub async fn compile(&self, code: &str, language: Language, options: &CompileOptions) -> Result<CompileOutcome> {
+        if matches!(language, Language::C | Language::Cpp) {
+            Compiler::ensure_toolchain(options.compiler_toolchain)
+                .await
+                .with_context(|| format!("{:?} toolchain is not installed", options.compiler_toolchain))?;
+        }
+        let compiler = Compiler::new().context("Failed to create compiler")?.with_cache(self.cache.clone());
+        let start = std::time::Instant::now();
+        let build = Self::build(&compiler, code, language, options, &[], STANDALONE_JAVA_HEAP_MB).await?;
+        Ok(CompileOutcome {
+            compile_time_ms: start.elapsed().as_millis() as u64,
+            executable_size_bytes: std::fs::metadata(build.artifact.path()).ok().map(|m| m.len()),
+            artifact: build.artifact,
+            warnings: build.warnings,
+            command: build.command,
+            cached: build.cached,
+            cached_build_time_ms: build.cached_build_time_ms,
+            _workspace: compiler,
+        })
+    }
+
+    /// Compile each `(code, language)` with default `CompileOptions` into the compile cache,
+    /// without running anything, so the first matching submissions skip the compiler
+    pub async fn warm_cache(&self, entries: Vec<(String, Language)>) -> WarmReport {
+        let mut report = WarmReport::default();
+        for (code, language) in entries {
+            if matches!(language, Language::Python | Language::Java) {
+                report.skipped += 1;
+                continue;
+            }
+            match self.compile(&code, language, &CompileOptions::default()).await {
+                Ok(outcome) if outcome.cached => report.already_cached += 1,
+                Ok(_) => report.built += 1,
+                Err(_) => report.failed += 1,
+            }
+        }
+        report
+    }
+
+    /// Run a known-good `reference_code` on every test case and list the cases whose
+    /// `expected_output` it doesn't reproduce. The problem's checker is ignored so the
+    /// stored answers themselves are compared; failing to build the reference is an error
+    pub async fn validate_testdata(&self, problem: &Problem, reference_code: &str, language: Language) -> Result<TestDataReport> {
+        let request = JudgeRequest {
+            code: reference_code.to_string(),
+            problem: Problem { checker: None, ..problem.clone() },
+            language,
+            normalization: NormalizationOptions::default(),
+            files: None,
+            extra_headers: Vec::new(),
+            stop_on_first_failure: false,
+            max_parallel_tests: None,
+            max_diff_lines: None,
+            max_compile_output_lines: None,
+            compile_timeout_ms: None,
+            max_source_bytes: None,
+            total_time_limit_ms: None,
+            compile_only: false,
+            reveal_hidden: true,
+            hidden_verdict_only: false,
+            stream: false,
+            progress: false,
+            structured_diagnostics: false,
+            compile_options: CompileOptions::default(),
+        };
+        let response = self.judge(request).await?;
+        let result = match response.result {
+            Some(result) if response.success => result,
+            _ => anyhow::bail!("Reference solution failed: {}", response.error.unwrap_or_default()),
+        };
+        let mismatches = result
+            .test_case_results
+            .into_iter()
+            .filter(|r| r.verdict != TestVerdict::Accepted)
+            .map(|r| TestDataMismatch {
+                test_case_id: r.test_case_id,
+                verdict: r.verdict,
+                expected_output: r.expected_output,
+                actual_output: r.actual_output,
+                diff: r.diff,
+                error: Some(r.execution_result.stderr).filter(|s| !s.is_empty()).or(r.execution_result.error),
+            })
+            .collect();
+        Ok(TestDataReport { total_test_cases: result.total_test_cases, mismatches })
     }
 
     /// Process a judge request and return results
     pub async fn judge(&self, request: JudgeRequest) -> Result<JudgeResponse> {
+        self.judge_with_progress(request, |_| {}).await
+    }
+
+    /// Blocking `judge` for callers without an async runtime; must not be called from inside one
+    pub fn judge_blocking(&self, request: JudgeRequest) -> Result<JudgeResponse> {
+        anyhow::ensure!(
+            tokio::runtime::Handle::try_current().is_err(),
+            "judge_blocking called from within an async runtime; use judge instead"
+        );
+        blocking_runtime()?.block_on(self.judge(request))
+    }
+
+    /// Like `judge`, calling `on_result` with each test case result as soon as it completes
+    pub async fn judge_with_progress<F>(&self, request: JudgeRequest, on_result: F) -> Result<JudgeResponse>
+    where
+        F: Fn(&TestCaseResult) + Sync,
+    {
+        self.judge_impl(request, on_result, None, Instant::now()).await
+    }
+
+    /// Judge many submissions, up to `workers` at a time. Each distinct checker is
+    /// compiled once and shared; responses come back in request order.
+    pub async fn judge_batch(&self, requests: Vec<JudgeRequest>, workers: usize) -> Vec<Result<JudgeResponse>> {
+        self.judge_batch_with_progress(requests, workers, |_| {}).await
+    }
+
+    /// Like `judge_batch`, calling `on_done` with each request's index as soon as it is judged
+    pub async fn judge_batch_with_progress<F>(&self, requests: Vec<JudgeRequest>, workers: usize, on_done: F) -> Vec<Result<JudgeResponse>>
+    where
+        F: Fn(usize) + Sync,
+    {
+        let queued_at = Instant::now();
+        // Keeps the checker binaries alive if they couldn't be moved into the cache
+        let compiler = match Compiler::new() {
+            Ok(compiler) => Some(compiler.with_cache(self.cache.clone())),
+            Err(_) => None,
+        };
+        let mut checkers: HashMap<(String, Option<u64>), Checker> = HashMap::new();
+        if let Some(compiler) = &compiler {
+            for spec in requests.iter().filter_map(|r| r.problem.checker.as_ref()) {
+                let key = (spec.source.clone(), spec.time_limit_ms);
+                if checkers.contains_key(&key) {
+                    continue;
+                }
+                // A checker that fails here is retried (and reported) by each request
+                if let Ok(checker) = Checker::compile(compiler, spec).await {
+                    checkers.insert(key, checker);
+                }
+            }
+        }
+
+        let (checkers, on_done) = (&checkers, &on_done);
+        stream::iter(requests.into_iter().enumerate())
+            .map(|(index, request)| async move {
+                let shared = request
+                    .problem
+                    .checker
+                    .as_ref()
+                    .and_then(|spec| checkers.get(&(spec.source.clone(), spec.time_limit_ms)));
+                let response = self.judge_impl(request, |_| {}, shared, queued_at).await;
+                on_done(index);
+                response
+            })
+            .buffered(workers.max(1))
+            .collect()
+            .await
+    }
+
+    /// Shared body of the judge entry points; `shared_checker` is a checker already
+    /// compiled for this request's `CheckerSpec`, `queued_at` when the caller handed it over
+    async fn judge_impl<F>(&self, request: JudgeRequest, on_result: F, shared_checker: Option<&Checker>, queued_at: Instant) -> Result<JudgeResponse>
+    where
+        F: Fn(&TestCaseResult) + Sync,
+    {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let _in_flight = InFlightGuard(&self.in_flight);
+        let response = self.judge_counted(request, on_result, shared_checker, queued_at).await;
+        self.judged_total.fetch_add(1, Ordering::Relaxed);
+        response
+    }
+
+    /// Body of `judge_impl`, which keeps the stats counters around it
+    async fn judge_counted<F>(&self, mut request: JudgeRequest, on_result: F, shared_checker: Option<&Checker>, queued_at: Instant) -> Result<JudgeResponse>
+    where
+        F: Fn(&TestCaseResult) + Sync,
+    {
+        let judge_start = Instant::now();
+        let queue_time_ms = judge_start.duration_since(queued_at).as_millis() as u64;
+        let deadline = request
+            .total_time_limit_ms
+            .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms));
+
+        // Only the native languages have project builds; anything else would judge `code` instead
+        if request.files.is_some() && !matches!(request.language, Language::C | Language::Cpp | Language::Rust) {
+            let message = format!("Multi-file projects aren't supported for {}; send the source as `code`", request.language);
+            return Ok(JudgeResponse::failure(OverallStatus::UnsupportedLanguage, ErrorCode::InvalidRequest, message));
+        }
+        let sources = match request.files {
+            Some(_) => Ok(()),
+            None => request.compile_options.check_sources(request.language, &request.extra_headers),
+        };
+        if let Err(e) = sources {
+            return Ok(JudgeResponse::failure(OverallStatus::InvalidRequest, ErrorCode::InvalidRequest, e.to_string()));
+        }
+
         // Initialize compiler
-        let compiler = Compiler::new().context("Failed to create compiler")?;
+        let mut compiler = Compiler::new()
+            .context("Failed to create compiler")?
+            .with_cache(self.cache.clone());
+        let compile_timeout = request.compile_timeout_ms.map(Duration::from_millis);
+        if let Some(limit) = compile_timeout {
+            compiler = compiler.with_compile_timeout(limit);
+        }
+        if let Some(max) = request.max_source_bytes {
+            compiler = compiler.with_max_source_bytes(max);
+        }
         
+        // Decompress test data, then reject input files outside the test-data root, bad
+        // patterns and weights before doing any work (compile-only requests never use them)
+        let decoded = match request.compile_only {
+            true => Ok(Vec::new()),
+            false => {
+                // Decompression is CPU-bound, so it runs off the async workers
+                let mut test_cases = std::mem::take(&mut request.problem.test_cases);
+                let binary = request.normalization.binary_output;
+                let decoded = tokio::task::spawn_blocking(move || {
+                    let decoded = test_cases
+                        .iter_mut()
+                        .enumerate()
+                        .map(|(i, test_case)| Self::decode_test_case(test_case, binary).with_context(|| format!("Test case {}", i)))
+                        .collect::<Result<Vec<_>>>();
+                    (test_cases, decoded)
+                })
+                .await
+                .context("Test data decoding panicked")?;
+                request.problem.test_cases = decoded.0;
+                decoded.1
+            }
+        };
+        let test_cases = if request.compile_only { &[][..] } else { &request.problem.test_cases[..] };
+        let prepared = decoded.and_then(|expected_bytes| {
+            if !request.compile_only {
+                Self::check_scoring(&request.problem)?;
+            }
+            let inputs = test_cases.iter().map(|t| self.resolve_input(&t.input)).collect::<Result<Vec<_>>>()?;
+            Ok((inputs, Self::compile_patterns(test_cases)?, expected_bytes))
+        });
+        let (inputs, patterns, expected_bytes) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                return Ok(JudgeResponse::failure(OverallStatus::EnvError, ErrorCode::InvalidTestData, format!("{:#}", e)));
+            }
+        };
+
+        let toolchain_missing = matches!(request.language, Language::C | Language::Cpp)
+            && request.files.is_none()
+            && Compiler::ensure_toolchain(request.compile_options.compiler_toolchain).await.is_err();
+
         // Compile the code
         let compile_start = std::time::Instant::now();
-        let executable_path = match request.language.to_lowercase().as_str() {
-            "c" => compiler.compile_c(&request.code).await,
-            "cpp" | "c++" => compiler.compile_cpp(&request.code).await,
-            _ => return Ok(JudgeResponse {
-                success: false,
-                result: None,
-                error: Some(format!("Unsupported language: {}", request.language)),
-                status: OverallStatus::UnsupportedLanguage,
-            }),
+        let language = request.language;
+        let artifact = match language {
+            Language::C | Language::Cpp | Language::Rust if request.files.is_some() => {
+                let files = request.files.clone().unwrap_or_default();
+                Self::compile_project(files, language, compile_timeout, self.retention.run_artifacts).await
+            }
+            Language::C | Language::Cpp if toolchain_missing => {
+                let message = format!("{:?} toolchain is not installed", request.compile_options.compiler_toolchain);
+                return Ok(JudgeResponse::failure(OverallStatus::EnvError, ErrorCode::ToolchainMissing, message));
+            }
+            _ => {
+                // The JVM heap size is fixed at build time, so it has to fit the most generous case
+                let java_heap_mb = request
+                    .problem
+                    .test_cases
+                    .iter()
+                    .filter_map(|t| t.memory_limit_mb)
+                    .fold(request.problem.memory_limit, u64::max);
+                Self::build(&compiler, &request.code, language, &request.compile_options, &request.extra_headers, java_heap_mb).await
+            }
         };
 
-        let executable_path = match executable_path {
-            Ok(path) => path,
+        let Build { artifact, warnings: compile_warnings, command: compile_command, cached: compilation_cached, cached_build_time_ms } = match artifact {
+            Ok(build) => build,
+            Err(e) if e.downcast_ref::<CompileTimeout>().is_some() => {
+                return Ok(JudgeResponse::failure(OverallStatus::CompileTimeout, ErrorCode::CompileTimeout, format!("Compilation timed out: {}", e)));
+            }
+            Err(e) if e.downcast_ref::<CompilerNotFound>().is_some() || e.downcast_ref::<UnsupportedStandard>().is_some() => {
+                return Ok(JudgeResponse::failure(OverallStatus::EnvError, ErrorCode::ToolchainMissing, e.to_string()));
+            }
+            Err(e) if e.downcast_ref::<CompileResourceLimit>().is_some() => {
+                let message = format!("Compile resource limit exceeded: {}", e);
+                return Ok(JudgeResponse::failure(OverallStatus::CompileResourceLimitExceeded, ErrorCode::CompileResourceLimit, message));
+            }
             Err(e) => {
+                let max_lines = request.max_compile_output_lines;
+                let (error, error_lines) = Self::truncate_compile_output(Some(format!("Compilation failed: {}", e)), max_lines);
+                let (compiler_output, output_lines) = Self::truncate_compile_output(
+                    e.downcast_ref::<CompileFailure>().and_then(|f| f.output.clone()),
+                    max_lines,
+                );
                 return Ok(JudgeResponse {
-                    success: false,
-                    result: None,
-                    error: Some(format!("Compilation failed: {}", e)),
-                    status: OverallStatus::CompileError,
+                    compiler_output,
+                    compile_command: e.downcast_ref::<CompileFailure>().map(|f| f.command.clone()),
+                    diagnostics: match e.downcast_ref::<CompileFailure>() {
+                        Some(failure) if request.structured_diagnostics => diagnostics::parse(&failure.stderr),
+                        _ => Vec::new(),
+                    },
+                    toolchain: e.downcast_ref::<CompileFailure>().map(|f| f.toolchain.clone()),
+                    compile_output_lines: error_lines.max(output_lines),
+                    ..JudgeResponse::failure(OverallStatus::CompileError, ErrorCode::CompileError, error.unwrap_or_default())
                 });
             }
         };
         let compile_time_ms = compile_start.elapsed().as_millis() as u64;
-        let executable_size_bytes = std::fs::metadata(&executable_path).ok().map(|m| m.len()).map(|n| n as u64);
+        // Held until the last case has run, so a long judge's binary can't be evicted under it
+        let _pin = compiler.pin(&artifact);
+        let warning_diagnostics = match &compile_warnings {
+            Some(warnings) if request.structured_diagnostics => diagnostics::parse(warnings),
+            _ => Vec::new(),
+        };
+        let (compile_warnings, compile_output_lines) =
+            Self::truncate_compile_output(compile_warnings, request.max_compile_output_lines);
+        let executable_size_bytes = std::fs::metadata(artifact.path()).ok().map(|m| m.len());
+        let debug_executable_path = (request.compile_options.debug_build
+            && request.files.is_none()
+            && matches!(language, Language::C | Language::Cpp))
+        .then(|| artifact.path().to_string());
 
-        // Execute test cases
-        let mut test_case_results = Vec::new();
-        let mut total_execution_time = 0u64;
+        if request.compile_only {
+            if request.files.is_some() {
+                let _ = std::fs::remove_file(artifact.path());
+            }
+            let judge_time_ms = judge_start.elapsed().as_millis() as u64;
+            return Ok(JudgeResponse {
+                success: true,
+                result: Some(SubmissionResult {
+                    problem_id: request.problem.id.clone(),
+                    total_test_cases: 0,
+                    passed_test_cases: 0,
+                    sample_test_cases: 0,
+                    sample_passed: 0,
+                    hidden_test_cases: 0,
+                    hidden_passed: 0,
+                    test_case_results: Vec::new(),
+                    compilation_successful: true,
+                    compilation_error: None,
+                    total_execution_time: 0,
+                    peak_memory_kb: 0,
+                    score: 0.0,
+                    subtask_results: Vec::new(),
+                    compile_time_ms: Some(compile_time_ms),
+                    queue_time_ms,
+                    comparison_time_ms: 0,
+                    judge_time_ms,
+                    overhead_ms: judge_time_ms.saturating_sub(compile_time_ms),
+                    compilation_cached,
+                    cached_build_time_ms,
+                    compile_warnings,
+                    executable_size_bytes,
+                    debug_executable_path,
+                }),
+                error: None,
+                status: OverallStatus::Compiled,
+                error_code: None,
+                compiler_output: None,
+                compile_command: Some(compile_command),
+                diagnostics: warning_diagnostics,
+                toolchain: None,
+                compile_output_lines,
+            });
+        }
 
-        for (i, test_case) in request.problem.test_cases.iter().enumerate() {
-            let executor = Executor::new(
-                request.problem.time_limit,
-                request.problem.memory_limit,
-            );
+        // Compile the special judge once, before any test case runs
+        let compiled_checker;
+        let checker = match (&request.problem.checker, shared_checker) {
+            (Some(_), Some(shared)) => Some(shared),
+            (Some(spec), None) => match Checker::compile(&compiler, spec).await {
+                Ok(checker) => {
+                    compiled_checker = checker;
+                    Some(&compiled_checker)
+                }
+                Err(e) => {
+                    return Ok(JudgeResponse::failure(OverallStatus::EnvError, ErrorCode::CheckerCompileError, format!("{:#}", e)));
+                }
+            },
+            (None, _) => None,
+        };
 
-            let execution_result = executor
-                .execute(&executable_path, &test_case.input)
-                .await
-                .unwrap_or_else(|e| ExecutionResult {
-                    success: false,
-                    output: String::new(),
-                    error: Some(format!("Execution error: {}", e)),
-                    execution_time: 0,
-                    memory_usage: 0,
-                });
+        let interactor = match &request.problem.interactor {
+            Some(source) => match Interactor::compile(&compiler, source).await {
+                Ok(interactor) => Some(interactor),
+                Err(e) => {
+                    return Ok(JudgeResponse::failure(OverallStatus::EnvError, ErrorCode::InteractorCompileError, format!("{:#}", e)));
+                }
+            },
+            None => None,
+        };
 
-            total_execution_time += execution_result.execution_time;
+        let normalized = NormalizedComparator::new(
+            request.normalization.clone(),
+            request.max_diff_lines.unwrap_or(DEFAULT_MAX_DIFF_LINES),
+        );
+        let comparator: &dyn Comparator = match (checker, &self.comparator) {
+            (Some(checker), _) => checker,
+            (None, Some(custom)) => custom.as_ref(),
+            (None, None) => &normalized,
+        };
 
-            // Compare outputs (with options)
-            let actual_output = self.normalize_output_with(&execution_result.output, &request.normalization);
-            let expected_output = self.normalize_output_with(&test_case.expected_output, &request.normalization);
-            let passed = actual_output == expected_output;
+        // Execute test cases, up to `max_parallel_tests` at a time
+        let run = TestRun {
+            request: &request,
+            artifact: &artifact,
+            checker,
+            interactor: interactor.as_ref(),
+            inputs: &inputs,
+            patterns: &patterns,
+            expected_bytes: &expected_bytes,
+            comparator,
+            // The JVM and sanitizer runtimes reserve far more address space than they use;
+            // -Xmx and the sampled peak cap them instead
+            address_space_limit: language != Language::Java && request.compile_options.sanitizers.is_empty(),
+            // JVM threads count toward RLIMIT_NPROC, so Java is only capped when the problem asks
+            process_limit: request.problem.process_limit.or((language != Language::Java).then_some(DEFAULT_PROCESS_LIMIT)),
+            spawn_failed: AtomicBool::new(false),
+            comparison_time_us: AtomicU64::new(0),
+        };
+        let parallelism = request.max_parallel_tests.unwrap_or(1).max(1);
+        let stopped = AtomicBool::new(false);
+        let out_of_time = AtomicBool::new(false);
+        // Summed before hidden cases are redacted, for `overhead_ms`
+        let executed_ms = AtomicU64::new(0);
 
-            test_case_results.push(TestCaseResult {
-                test_case_id: i,
-                passed,
-                execution_result: execution_result.clone(),
-                expected_output: test_case.expected_output.clone(),
-                actual_output: execution_result.output.clone(),
-            });
-        }
+        let mut test_case_results: Vec<TestCaseResult> = stream::iter(0..request.problem.test_cases.len())
+            .map(|i| {
+                // Indexing instead of iterating by reference keeps the future `Send`-provable
+                let test_case = &request.problem.test_cases[i];
+                let (run, stopped, out_of_time, executed_ms, on_result) = (&run, &stopped, &out_of_time, &executed_ms, &on_result);
+                async move {
+                    // Past the deadline a running case is dropped, which kills its process group
+                    let result = if stopped.load(Ordering::Relaxed) {
+                        None
+                    } else if let Some(deadline) = deadline {
+                        if tokio::time::Instant::now() < deadline {
+                            tokio::time::timeout_at(deadline, self.run_test_case(run, i, test_case)).await.ok()
+                        } else {
+                            None
+                        }
+                    } else {
+                        Some(self.run_test_case(run, i, test_case).await)
+                    };
+                    let mut result = match result {
+                        Some(result) => {
+                            if result.verdict != TestVerdict::Accepted && run.request.stop_on_first_failure {
+                                stopped.store(true, Ordering::Relaxed);
+                            }
+                            result
+                        }
+                        None => {
+                            if !stopped.load(Ordering::Relaxed) {
+                                out_of_time.store(true, Ordering::Relaxed);
+                                stopped.store(true, Ordering::Relaxed);
+                            }
+                            Self::skipped_result(i, test_case)
+                        }
+                    };
+                    executed_ms.fetch_add(result.execution_result.execution_time, Ordering::Relaxed);
+                    if test_case.is_hidden && !run.request.reveal_hidden {
+                        Self::redact(&mut result);
+                        if run.request.hidden_verdict_only {
+                            Self::keep_verdict_only(&mut result);
+                        }
+                    }
+                    on_result(&result);
+                    result
+                }
+            })
+            .buffer_unordered(parallelism)
+            .collect()
+            .await;
+        test_case_results.sort_by_key(|r| r.test_case_id);
+        let total_execution_time: u64 = test_case_results
+            .iter()
+            .map(|r| r.execution_result.execution_time)
+            .sum();
+        let peak_memory_kb = test_case_results
+            .iter()
+            .map(|r| r.execution_result.memory_usage)
+            .max()
+            .unwrap_or(0);
 
         // Calculate score
         let passed_count = test_case_results.iter().filter(|r| r.passed).count();
-        let score = (passed_count as f64 / test_case_results.len() as f64) * 100.0;
+        let is_hidden = |r: &&TestCaseResult| request.problem.test_cases[r.test_case_id].is_hidden;
+        let hidden_test_cases = test_case_results.iter().filter(is_hidden).count();
+        let hidden_passed = test_case_results.iter().filter(is_hidden).filter(|r| r.passed).count();
+        let subtask_results = Self::score_subtasks(&request.problem.subtasks, &test_case_results);
+        let score = if subtask_results.is_empty() {
+            let weight_of = |i: usize| request.problem.test_cases[i].weight.unwrap_or(1.0);
+            let total_weight: f64 = (0..test_case_results.len()).map(weight_of).sum();
+            let passed_weight: f64 = test_case_results
+                .iter()
+                .map(|r| weight_of(r.test_case_id) * r.points)
+                .sum();
+            // No cases (or only zero-weight ones) would divide by zero
+            if total_weight > 0.0 { passed_weight / total_weight * 100.0 } else { 0.0 }
+        } else {
+            let earned: f64 = subtask_results.iter().map(|s| s.points).sum();
+            let total: f64 = subtask_results.iter().map(|s| s.max_points).sum();
+            if total > 0.0 { earned / total * 100.0 } else { 0.0 }
+        };
 
-        let overall_status = if passed_count == test_case_results.len() {
+        // Derived from the verdicts, which already put the exit status ahead of the output
+        let any = |verdicts: &[TestVerdict]| test_case_results.iter().any(|r| verdicts.contains(&r.verdict));
+        let overall_status = if test_case_results.is_empty() {
+            OverallStatus::NoTestCases
+        } else if out_of_time.load(Ordering::Relaxed) {
+            OverallStatus::TotalTimeLimitExceeded
+        } else if passed_count == test_case_results.len() {
             OverallStatus::Ok
-        } else if test_case_results.iter().any(|r| r.execution_result.error.as_deref() == Some("Time limit exceeded")) {
+        } else if any(&[TestVerdict::JudgeError]) {
+            // A case the checker couldn't judge says nothing about the submission
+            OverallStatus::EnvError
+        } else if any(&[TestVerdict::TimeLimitExceeded]) {
             OverallStatus::Timeout
-        } else if test_case_results.iter().any(|r| r.execution_result.success == false && r.execution_result.error.is_some()) {
+        } else if any(&[TestVerdict::IdlenessLimitExceeded]) {
+            OverallStatus::IdlenessLimitExceeded
+        } else if any(&[TestVerdict::MemoryLimitExceeded]) {
+            OverallStatus::MemoryLimitExceeded
+        } else if any(&[TestVerdict::OutputLimitExceeded]) {
+            OverallStatus::OutputLimitExceeded
+        } else if any(&[TestVerdict::RuntimeError, TestVerdict::SanitizerError]) {
             OverallStatus::RuntimeError
         } else {
             OverallStatus::Ok
         };
+        let error_code = if any(&[TestVerdict::JudgeError]) {
+            Some(if run.interactor.is_some() { ErrorCode::InteractorFailed } else { ErrorCode::CheckerFailed })
+        } else {
+            run.spawn_failed.load(Ordering::Relaxed).then_some(ErrorCode::SpawnFailed)
+        };
 
+        let comparison_time_ms = run.comparison_time_us.load(Ordering::Relaxed) / 1000;
+        let judge_time_ms = judge_start.elapsed().as_millis() as u64;
         let submission_result = SubmissionResult {
             problem_id: request.problem.id.clone(),
             total_test_cases: test_case_results.len(),
             passed_test_cases: passed_count,
+            sample_test_cases: test_case_results.len() - hidden_test_cases,
+            sample_passed: passed_count - hidden_passed,
+            hidden_test_cases,
+            hidden_passed,
             test_case_results,
             compilation_successful: true,
             compilation_error: None,
             total_execution_time,
+            peak_memory_kb,
             score,
+            subtask_results,
             compile_time_ms: Some(compile_time_ms),
+            queue_time_ms,
+            comparison_time_ms,
+            judge_time_ms,
+            overhead_ms: judge_time_ms.saturating_sub(compile_time_ms + executed_ms.into_inner() + comparison_time_ms),
+            compilation_cached,
+            cached_build_time_ms,
+            compile_warnings,
             executable_size_bytes,
+            debug_executable_path,
         };
 
+        // Project builds live outside the compile cache; drop them once judged
+        if request.files.is_some() {
+            let _ = std::fs::remove_file(artifact.path());
+        }
+
         Ok(JudgeResponse {
             success: true,
             result: Some(submission_result),
             error: None,
             status: overall_status,
+            error_code,
+            compiler_output: None,
+            compile_command: Some(compile_command),
+            diagnostics: warning_diagnostics,
+            toolchain: None,
+            compile_output_lines,
         })
     }
 
-    fn normalize_output_with(&self, output: &str, opts: &NormalizationOptions) -> String {
-        let mut s = output.to_string();
-        if opts.normalize_crlf { s = s.replace("\r\n", "\n"); }
-        if opts.ignore_extra_whitespace {
-            s = s
-                .lines()
-                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
-                .collect::<Vec<_>>()
-                .join("\n");
+    /// Run one test case and judge its output
+    async fn run_test_case(&self, run: &TestRun<'_>, i: usize, test_case: &TestCase) -> TestCaseResult {
+        let request = run.request;
+        if let Some(interactor) = run.interactor {
+            return self.judge_interactive(run, i, test_case, interactor).await;
+        }
+        let time_limit = test_case.time_limit_ms.unwrap_or(request.problem.time_limit);
+        let memory_limit = test_case.memory_limit_mb.unwrap_or(request.problem.memory_limit);
+        let env = request
+            .compile_options
+            .runtime_env()
+            .into_iter()
+            .chain(request.problem.env.iter().cloned())
+            .chain(Self::seed_env(test_case));
+        let executor = env.fold(
+            Executor::new(time_limit, memory_limit)
+                .with_address_space_limit(run.address_space_limit)
+                .with_time_limit_mode(request.problem.time_limit_mode)
+                .with_network(request.problem.allow_network)
+                .with_process_limit(run.process_limit)
+                .with_idle_limit(request.problem.idle_limit_ms)
+                .with_memory_sampling(request.problem.memory_sampling)
+                .with_io_mode(request.problem.io_mode)
+                .with_cpu_affinity(self.cpu_core)
+                .with_args(request.problem.args.iter().cloned())
+                .with_output_limit(request.problem.output_limit_bytes.unwrap_or(DEFAULT_OUTPUT_LIMIT_BYTES))
+                .with_ubsan_detection(request.compile_options.sanitizers.contains(&Sanitizer::Undefined)),
+            |executor, (key, value)| executor.with_env(key, value),
+        );
+
+        let mut execution_result = executor
+            .execute_input(run.artifact, &run.inputs[i])
+            .await
+            .unwrap_or_else(|e| {
+                run.spawn_failed.store(true, Ordering::Relaxed);
+                ExecutionResult {
+                    success: false,
+                    output: String::new(),
+                    output_bytes: Vec::new(),
+                    error: Some(format!("Execution error: {}", e)),
+                    stderr: String::new(),
+                    execution_time: 0,
+                    cpu_time_ms: None,
+                    memory_usage: 0,
+                }
+            });
+
+        // Peak usage over the budget fails the case even if the process survived
+        let memory_limit_kb = memory_limit * 1024;
+        if memory_limit_kb > 0 && execution_result.memory_usage > memory_limit_kb {
+            execution_result.success = false;
+            execution_result.error = Some(MEMORY_LIMIT_EXCEEDED.to_string());
+        }
+
+        // Execution failures decide the verdict; otherwise compare outputs
+        let failure = match execution_result.error.as_deref() {
+            Some(TIME_LIMIT_EXCEEDED) => Some(TestVerdict::TimeLimitExceeded),
+            Some(IDLENESS_LIMIT_EXCEEDED) => Some(TestVerdict::IdlenessLimitExceeded),
+            Some(MEMORY_LIMIT_EXCEEDED) => Some(TestVerdict::MemoryLimitExceeded),
+            Some(OUTPUT_LIMIT_EXCEEDED) => Some(TestVerdict::OutputLimitExceeded),
+            Some(_) if execution_result.stderr.contains("ERROR: AddressSanitizer") => Some(TestVerdict::SanitizerError),
+            _ if !execution_result.success => Some(TestVerdict::RuntimeError),
+            _ => None,
+        };
+        // A custom checker takes precedence over the problem's expected-output settings
+        let pattern = run.patterns[i].as_ref().filter(|_| run.checker.is_none());
+        let comparison_start = Instant::now();
+        let comparison = match (failure, pattern) {
+            (Some(verdict), _) => Comparison { verdict, ..Comparison::from_match(false) },
+            (None, Some(pattern)) => {
+                Comparison::from_match(pattern.is_match(&comparator::normalize(&execution_result.output, &request.normalization)))
+            }
+            (None, None) if run.checker.is_none() && request.normalization.binary_output => {
+                let output = &execution_result.output_bytes;
+                Comparison::from_match(match &run.expected_bytes[i] {
+                    Some(expected) => expected.iter().any(|bytes| output == bytes),
+                    None => test_case.expected_outputs().any(|text| output == text.as_bytes()),
+                })
+            }
+            (None, None) => Self::compare_expected(run.comparator, &run.inputs[i], test_case, &execution_result.output).await,
+        };
+        run.comparison_time_us.fetch_add(comparison_start.elapsed().as_micros() as u64, Ordering::Relaxed);
+        let verdict = comparison.verdict;
+
+        TestCaseResult {
+            test_case_id: i,
+            passed: verdict == TestVerdict::Accepted,
+            verdict,
+            points: Self::points_for(verdict, comparison.points),
+            slow: Self::is_slow(&request.problem, verdict, &execution_result),
+            execution_result: execution_result.clone(),
+            expected_output: test_case.expected_output.clone(),
+            actual_output: execution_result.output.clone(),
+            checker_message: comparison.message,
+            first_diff_token: comparison.first_diff_token,
+            first_diff_line: comparison.first_diff_line,
+            diff: comparison.diff,
+        }
+    }
+
+    /// Whether an accepted run went over the soft time limit, charged like the hard one
+    fn is_slow(problem: &Problem, verdict: TestVerdict, execution_result: &ExecutionResult) -> bool {
+        let charged = match problem.time_limit_mode {
+            TimeLimitMode::Wall => execution_result.execution_time,
+            TimeLimitMode::Cpu => execution_result.cpu_time_ms.unwrap_or(execution_result.execution_time),
+        };
+        verdict == TestVerdict::Accepted && problem.soft_time_limit_ms.is_some_and(|soft| charged > soft)
+    }
+
+    /// `JUDGE_SEED` for a test case that has a seed
+    fn seed_env(test_case: &TestCase) -> Option<(String, String)> {
+        test_case.seed.map(|seed| (SEED_ENV_VAR.to_string(), seed.to_string()))
+    }
+
+    /// Compare against `expected_output`, then each of `accepted_outputs` until one is
+    /// accepted; mismatch details always refer to `expected_output`
+    async fn compare_expected(comparator: &dyn Comparator, input: &ProgramInput<'_>, test_case: &TestCase, actual: &str) -> Comparison {
+        let comparison = comparator.compare_seeded(input, &test_case.expected_output, actual, test_case.seed).await;
+        if comparison.verdict == TestVerdict::Accepted {
+            return comparison;
+        }
+        for alternative in &test_case.accepted_outputs {
+            let alternative = comparator.compare_seeded(input, alternative, actual, test_case.seed).await;
+            if alternative.verdict == TestVerdict::Accepted {
+                return alternative;
+            }
+        }
+        comparison
+    }
+
+    /// Points for a case: the comparator's score when it gave one, else all or nothing
+    fn points_for(verdict: TestVerdict, points: Option<f64>) -> f64 {
+        match verdict {
+            TestVerdict::Accepted | TestVerdict::PartiallyCorrect => points.unwrap_or(1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Run one test case against the problem's interactor, which decides the verdict
+    async fn judge_interactive(&self, run: &TestRun<'_>, i: usize, test_case: &TestCase, interactor: &Interactor) -> TestCaseResult {
+        let problem = &run.request.problem;
+        let memory_limit = test_case.memory_limit_mb.unwrap_or(problem.memory_limit);
+        let limits = SolutionLimits {
+            time_limit_ms: test_case.time_limit_ms.unwrap_or(problem.time_limit),
+            address_space_limit_bytes: (run.address_space_limit && memory_limit > 0)
+                .then_some(memory_limit * 1024 * 1024),
+            process_limit: run.process_limit,
+            idle_limit_ms: problem.idle_limit_ms,
+            allow_network: problem.allow_network,
+            args: problem.args.clone(),
+            env: run
+                .request
+                .compile_options
+                .runtime_env()
+                .into_iter()
+                .chain(problem.env.iter().cloned())
+                .chain(Self::seed_env(test_case))
+                .collect(),
+        };
+        let (mut execution_result, outcome) = match interactor
+            .run(run.artifact, &run.inputs[i], &test_case.expected_output, &limits)
+            .await
+        {
+            Ok(interaction) => (interaction.execution, interaction.outcome),
+            Err(e) => {
+                run.spawn_failed.store(true, Ordering::Relaxed);
+                (
+                    ExecutionResult {
+                        success: false,
+                        output: String::new(),
+                        output_bytes: Vec::new(),
+                        error: Some(format!("Execution error: {}", e)),
+                        stderr: String::new(),
+                        execution_time: 0,
+                        cpu_time_ms: None,
+                        memory_usage: 0,
+                    },
+                    Err(e),
+                )
+            }
+        };
+
+        // Same post-run checks as a regular run: peak memory and sanitizer reports
+        let memory_limit_kb = memory_limit * 1024;
+        if memory_limit_kb > 0 && execution_result.memory_usage > memory_limit_kb {
+            execution_result.success = false;
+            execution_result.error = Some(MEMORY_LIMIT_EXCEEDED.to_string());
+        } else if execution_result.success
+            && run.request.compile_options.sanitizers.contains(&Sanitizer::Undefined)
+            && execution_result.stderr.contains(UBSAN_MARKER)
+        {
+            execution_result.success = false;
+            execution_result.error = Some(UNDEFINED_BEHAVIOR.to_string());
+        }
+
+        let mut checker_message = None;
+        let mut checker_points = None;
+        let verdict = match execution_result.error.as_deref() {
+            Some(TIME_LIMIT_EXCEEDED) => TestVerdict::TimeLimitExceeded,
+            Some(IDLENESS_LIMIT_EXCEEDED) => TestVerdict::IdlenessLimitExceeded,
+            Some(MEMORY_LIMIT_EXCEEDED) => TestVerdict::MemoryLimitExceeded,
+            Some(_) if execution_result.stderr.contains("ERROR: AddressSanitizer") => TestVerdict::SanitizerError,
+            _ if !execution_result.success => TestVerdict::RuntimeError,
+            _ => match outcome {
+                Ok(outcome) => {
+                    checker_points = Some(outcome.points());
+                    let verdict = outcome.verdict();
+                    checker_message = outcome.message;
+                    verdict
+                }
+                // The interactor's own failure says nothing about the solution
+                Err(e) => {
+                    checker_message = Some(e.to_string());
+                    TestVerdict::JudgeError
+                }
+            },
+        };
+
+        TestCaseResult {
+            test_case_id: i,
+            passed: verdict == TestVerdict::Accepted,
+            verdict,
+            points: Self::points_for(verdict, checker_points),
+            slow: Self::is_slow(&run.request.problem, verdict, &execution_result),
+            execution_result,
+            expected_output: test_case.expected_output.clone(),
+            actual_output: String::new(),
+            checker_message,
+            first_diff_token: None,
+            first_diff_line: None,
+            diff: None,
+        }
+    }
+
+    /// Replace an encoded case's inline input and outputs with their decoded text. With
+    /// `binary` the outputs may be any bytes: those are returned for comparison, and the
+    /// text keeps a lossy copy for the response
+    fn decode_test_case(test_case: &mut TestCase, binary: bool) -> Result<Option<Vec<Vec<u8>>>> {
+        let encoding = test_case.encoding;
+        if encoding == TestDataEncoding::Plain {
+            return Ok(None);
+        }
+        if let TestCaseSource::Inline(input) = &mut test_case.input {
+            *input = String::from_utf8(Self::decode_bytes(input, encoding)?).context("not valid UTF-8").context("Invalid input")?;
+        }
+        let mut expected_bytes = Vec::new();
+        let outputs = std::iter::once(&mut test_case.expected_output).chain(&mut test_case.accepted_outputs);
+        for (i, output) in outputs.enumerate() {
+            let field = if i == 0 { "expected_output" } else { "accepted output" };
+            let bytes = Self::decode_bytes(output, encoding).with_context(|| format!("Invalid {}", field))?;
+            *output = match binary {
+                true => {
+                    let text = String::from_utf8_lossy(&bytes).into_owned();
+                    expected_bytes.push(bytes);
+                    text
+                }
+                false => String::from_utf8(bytes).context("not valid UTF-8").with_context(|| format!("Invalid {}", field))?,
+            };
+        }
+        test_case.encoding = TestDataEncoding::Plain;
+        Ok(binary.then_some(expected_bytes))
+    }
+
+    /// Decode base64 (line breaks allowed), then gunzip it for `Gzip`
+    fn decode_bytes(data: &str, encoding: TestDataEncoding) -> Result<Vec<u8>> {
+        let decoded = BASE64
+            .decode(data.split_whitespace().collect::<String>())
+            .context("not valid base64")?;
+        if encoding != TestDataEncoding::Gzip {
+            return Ok(decoded);
+        }
+        let mut bytes = Vec::new();
+        GzDecoder::new(&decoded[..])
+            .take(MAX_DECODED_TEST_DATA_BYTES + 1)
+            .read_to_end(&mut bytes)
+            .context("not valid gzip data")?;
+        if bytes.len() as u64 > MAX_DECODED_TEST_DATA_BYTES {
+            anyhow::bail!("decompresses to more than {} MB", MAX_DECODED_TEST_DATA_BYTES / (1024 * 1024));
+        }
+        Ok(bytes)
+    }
+
+    /// Stdin for a test case; file inputs must resolve to a path under the test-data root
+    fn resolve_input<'a>(&self, source: &'a TestCaseSource) -> Result<ProgramInput<'a>> {
+        let path = match source {
+            TestCaseSource::Inline(text) => return Ok(ProgramInput::Text(text)),
+            TestCaseSource::File { path } => path,
+        };
+        let root = self
+            .test_data_root
+            .as_ref()
+            .context("Test input files need a test-data root (DSA_JUDGE_TEST_DATA_ROOT)")?
+            .canonicalize()
+            .context("Test-data root is not accessible")?;
+        let resolved = root
+            .join(path)
+            .canonicalize()
+            .with_context(|| format!("Test input file not found: {}", path.display()))?;
+        anyhow::ensure!(
+            resolved.starts_with(&root) && resolved.is_file(),
+            "Test input file {} is not a file under the test-data root",
+            path.display()
+        );
+        Ok(ProgramInput::File(resolved))
+    }
+
+    /// Reject test case weights and subtask points that are negative or not finite, and
+    /// subtask test indices that are out of range or repeated, which would make the score
+    /// meaningless
+    fn check_scoring(problem: &Problem) -> Result<()> {
+        let valid = |weight: f64| weight.is_finite() && weight >= 0.0;
+        for (i, test_case) in problem.test_cases.iter().enumerate() {
+            if let Some(weight) = test_case.weight.filter(|&w| !valid(w)) {
+                anyhow::bail!("Invalid weight {} in test case {}: must be finite and at least 0", weight, i);
+            }
+        }
+        for (i, subtask) in problem.subtasks.iter().enumerate() {
+            if !valid(subtask.points) {
+                anyhow::bail!("Invalid points {} in subtask {}: must be finite and at least 0", subtask.points, i);
+            }
+            let mut seen = HashSet::new();
+            for &index in &subtask.test_cases {
+                if index >= problem.test_cases.len() {
+                    anyhow::bail!("Subtask {} refers to test case {}, but there are only {}", i, index, problem.test_cases.len());
+                }
+                if !seen.insert(index) {
+                    anyhow::bail!("Subtask {} lists test case {} more than once", i, index);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile each case's `expected_regex`, anchored to match the whole output
+    fn compile_patterns(test_cases: &[TestCase]) -> Result<Vec<Option<Regex>>> {
+        test_cases
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                t.expected_regex
+                    .as_deref()
+                    .map(|pattern| {
+                        Regex::new(&format!("^(?:{})$", pattern))
+                            .with_context(|| format!("Invalid expected_regex in test case {}", i))
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Strip everything derived from a hidden case's data, keeping verdict, time and memory.
+    /// Limit errors say nothing about the data, so they stay.
+    fn redact(result: &mut TestCaseResult) {
+        let execution = &mut result.execution_result;
+        execution.output.clear();
+        execution.output_bytes.clear();
+        execution.stderr.clear();
+        execution.error = match execution.error.take().as_deref() {
+            Some(limit @ (TIME_LIMIT_EXCEEDED | IDLENESS_LIMIT_EXCEEDED | MEMORY_LIMIT_EXCEEDED | OUTPUT_LIMIT_EXCEEDED)) => {
+                Some(limit.to_string())
+            }
+            Some(_) => Some(HIDDEN_DETAILS.to_string()),
+            None => None,
+        };
+        result.expected_output.clear();
+        result.actual_output.clear();
+        result.checker_message = None;
+        result.first_diff_token = None;
+        result.first_diff_line = None;
+        result.diff = None;
+    }
+
+    /// After `redact`, also drop the time, memory and limit message, leaving the verdict and points
+    fn keep_verdict_only(result: &mut TestCaseResult) {
+        let execution = &mut result.execution_result;
+        execution.error = None;
+        execution.execution_time = 0;
+        execution.cpu_time_ms = None;
+        execution.memory_usage = 0;
+        result.slow = false;
+    }
+
+    /// Placeholder result for a test case that was never run
+    fn skipped_result(test_case_id: usize, test_case: &TestCase) -> TestCaseResult {
+        TestCaseResult {
+            test_case_id,
+            passed: false,
+            verdict: TestVerdict::Skipped,
+            points: 0.0,
+            slow: false,
+            execution_result: ExecutionResult {
+                success: false,
+                output: String::new(),
+                output_bytes: Vec::new(),
+                error: None,
+                stderr: String::new(),
+                execution_time: 0,
+                cpu_time_ms: None,
+                memory_usage: 0,
+            },
+            expected_output: test_case.expected_output.clone(),
+            actual_output: String::new(),
+            checker_message: None,
+            first_diff_token: None,
+            first_diff_line: None,
+            diff: None,
+        }
+    }
+
+    /// Score each subtask by its lowest-scoring case; a subtask referencing a missing case fails
+    fn score_subtasks(subtasks: &[Subtask], results: &[TestCaseResult]) -> Vec<SubtaskResult> {
+        subtasks
+            .iter()
+            .enumerate()
+            .map(|(id, subtask)| {
+                let passed = subtask
+                    .test_cases
+                    .iter()
+                    .all(|&i| results.get(i).is_some_and(|r| r.passed));
+                // A subtask is only as good as its weakest case, so partial credit carries over
+                let fraction = subtask
+                    .test_cases
+                    .iter()
+                    .map(|&i| results.get(i).map_or(0.0, |r| r.points))
+                    .fold(1.0, f64::min);
+                SubtaskResult {
+                    subtask_id: id,
+                    passed,
+                    points: subtask.points * fraction,
+                    max_points: subtask.points,
+                }
+            })
+            .collect()
+    }
+
+    /// Compile single-file source with an already configured compiler
+    async fn build(
+        compiler: &Compiler,
+        code: &str,
+        language: Language,
+        options: &CompileOptions,
+        headers: &[CodeFile],
+        java_heap_mb: u64,
+    ) -> Result<Build> {
+        match language {
+            Language::C => compiler.compile_c(code, options, headers).await,
+            Language::Cpp => compiler.compile_cpp(code, options, headers).await,
+            Language::Python => compiler.compile_python(code).await,
+            Language::Java => compiler.compile_java(code, java_heap_mb).await,
+            Language::Rust => compiler.compile_rust(code).await,
+        }
+    }
+
+    /// Compiler output cut to `max_lines`, and its original line count if it was cut
+    fn truncate_compile_output(output: Option<String>, max_lines: Option<usize>) -> (Option<String>, Option<usize>) {
+        match (output, max_lines) {
+            (Some(text), Some(max)) => match diagnostics::truncate_lines(&text, max) {
+                Some((cut, total)) => (Some(cut), Some(total)),
+                None => (Some(text), None),
+            },
+            (output, _) => (output, None),
         }
-        s.lines().map(|l| l.trim()).collect::<Vec<_>>().join("\n").trim().to_string()
+    }
+
+    /// Build a multi-file project through `interactive::compile_files`, dropping run
+    /// binaries older than `run_artifact_max_age`
+    async fn compile_project(
+        files: Vec<CodeFile>,
+        language: Language,
+        compile_timeout: Option<Duration>,
+        run_artifact_max_age: Duration,
+    ) -> Result<Build> {
+        let compile_timeout = compile_timeout.unwrap_or(PROJECT_COMPILE_TIMEOUT);
+        let result = compile_files_retaining(files, language, compile_timeout, run_artifact_max_age).await?;
+        match result.executable_path {
+            Some(path) if result.success => Ok(Build { artifact: Artifact::Native { path }, warnings: None, command: result.command, cached: false, cached_build_time_ms: None }),
+            _ => Err(CompileFailure {
+                message: format!("Compilation failed: {}", result.error.as_deref().unwrap_or_default()),
+                output: None,
+                command: result.command,
+                stderr: result.error.unwrap_or_default(),
+                toolchain: result.toolchain.unwrap_or_else(|| language.to_string()),
+            }
+            .into()),
+        }
+    }
+
+    /// Check that a specific C/C++ toolchain is installed
+    pub fn check_toolchain(toolchain: Toolchain) -> Result<()> {
+        Compiler::check_toolchain(toolchain)
     }
 
     /// Check if required tools are available
@@ -143,3 +1364,170 @@ impl Judge {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interactive::run_artifact_aged;
+    use serde_json::json;
+
+    /// A request for `code` against a problem with the given test cases
+    fn request(language: &str, code: &str, test_cases: serde_json::Value) -> JudgeRequest {
+        serde_json::from_value(json!({
+            "code": code,
+            "language": language,
+            "problem": {
+                "id": "p", "title": "t", "description": "d", "difficulty": "Easy",
+                "time_limit": 2000, "memory_limit": 256, "tags": [], "test_cases": test_cases,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn empty_test_cases_give_a_finite_score() {
+        let response = Judge::new().unwrap().judge(request("c", "int main(void) { return 0; }", json!([]))).await.unwrap();
+        assert!(matches!(response.status, OverallStatus::NoTestCases), "{:?}", response.status);
+        let result = response.result.unwrap();
+        assert!(result.score.is_finite());
+        assert_eq!(result.score, 0.0);
+    }
+
+    #[tokio::test]
+    async fn negative_weights_are_rejected() {
+        let cases = json!([{ "input": "", "expected_output": "1", "is_hidden": false, "weight": -1.0 }]);
+        let response = Judge::new().unwrap().judge(request("c", "int main(void) { return 0; }", cases)).await.unwrap();
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidTestData));
+        assert!(response.error.unwrap().contains("weight"));
+    }
+
+    #[tokio::test]
+    async fn subtask_indices_are_checked() {
+        let cases = json!([{ "input": "", "expected_output": "", "is_hidden": false }]);
+        for (indices, complaint) in [(json!([0, 1]), "only 1"), (json!([0, 0]), "more than once")] {
+            let mut request = request("c", "int main(void) { return 0; }", cases.clone());
+            request.problem.subtasks = serde_json::from_value(json!([{ "test_cases": indices, "points": 10.0 }])).unwrap();
+            let response = Judge::new().unwrap().judge(request).await.unwrap();
+            assert_eq!(response.error_code, Some(ErrorCode::InvalidTestData));
+            assert!(response.error.unwrap().contains(complaint));
+        }
+    }
+
+    #[tokio::test]
+    async fn files_are_rejected_for_interpreted_languages() {
+        let mut request = request("python", "print(1)", json!([]));
+        request.files = Some(vec![CodeFile { filename: "main.py".to_string(), content: "print(2)".to_string() }]);
+        let response = Judge::new().unwrap().judge(request).await.unwrap();
+        assert!(!response.success);
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidRequest));
+    }
+
+    #[tokio::test]
+    async fn bad_source_filename_is_an_invalid_request() {
+        let mut request = request("c", "int main(void) { return 0; }", json!([]));
+        request.compile_options.source_filename = Some("../main.c".to_string());
+        let response = Judge::new().unwrap().judge(request.clone()).await.unwrap();
+        assert!(matches!(response.status, OverallStatus::InvalidRequest));
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidRequest));
+
+        request.compile_options.source_filename = Some("main.c".to_string());
+        request.extra_headers = vec![CodeFile { filename: "helper.c".to_string(), content: String::new() }];
+        let response = Judge::new().unwrap().judge(request).await.unwrap();
+        assert_eq!(response.error_code, Some(ErrorCode::InvalidRequest));
+    }
+
+    /// Verdict of the single case `{"input": "", "expected_output": "1"}` for `code` in C
+    async fn single_verdict(code: &str) -> TestCaseResult {
+        let cases = json!([{ "input": "", "expected_output": "1", "is_hidden": false }]);
+        let response = Judge::new().unwrap().judge(request("c", code, cases)).await.unwrap();
+        response.result.unwrap().test_case_results.remove(0)
+    }
+
+    #[tokio::test]
+    async fn correct_output_then_crash_is_a_runtime_error() {
+        let code = "#include <stdio.h>\n#include <stdlib.h>\nint main(void) { printf(\"1\\n\"); fflush(stdout); abort(); }";
+        let result = single_verdict(code).await;
+        assert_eq!(result.actual_output, "1\n");
+        assert_eq!(result.verdict, TestVerdict::RuntimeError);
+    }
+
+    #[tokio::test]
+    async fn claiming_to_be_out_of_memory_is_still_a_runtime_error() {
+        let code = "#include <stdio.h>\nint main(void) { fputs(\"std::bad_alloc: out of memory\\n\", stderr); return 1; }";
+        let result = single_verdict(code).await;
+        assert_eq!(result.verdict, TestVerdict::RuntimeError);
+    }
+
+    #[tokio::test]
+    async fn nonzero_exit_with_empty_stderr_is_a_runtime_error() {
+        let result = single_verdict("#include <stdio.h>\nint main(void) { printf(\"1\\n\"); return 3; }").await;
+        assert!(result.execution_result.stderr.is_empty());
+        assert_eq!(result.verdict, TestVerdict::RuntimeError);
+        assert_eq!(result.execution_result.error.as_deref(), Some("Exited with code 3"));
+    }
+
+    #[tokio::test]
+    async fn redacted_run_time_is_not_counted_as_overhead() {
+        let code = "#include <stdio.h>\n#include <unistd.h>\nint main(void) { usleep(800000); printf(\"1\\n\"); return 0; }";
+        let cases = json!([{ "input": "", "expected_output": "1", "is_hidden": true }]);
+        let mut request = request("c", code, cases);
+        request.hidden_verdict_only = true;
+        let result = Judge::new().unwrap().judge(request).await.unwrap().result.unwrap();
+        assert_eq!(result.test_case_results[0].execution_result.execution_time, 0);
+        assert!(result.overhead_ms < 500, "overhead_ms = {}", result.overhead_ms);
+    }
+
+    #[tokio::test]
+    async fn binary_output_compares_decoded_bytes() {
+        let code = "#include <stdio.h>\nint main(void) { fwrite(\"\\xff\\x00\\x01\", 1, 3, stdout); return 0; }";
+        let cases = json!([
+            { "input": "", "expected_output": "/wAB", "encoding": "base64", "is_hidden": false },
+            { "input": "", "expected_output": "/wAC", "encoding": "base64", "is_hidden": false },
+        ]);
+        let mut request = request("c", code, cases);
+        request.normalization.binary_output = true;
+        let result = Judge::new().unwrap().judge(request).await.unwrap().result.unwrap();
+        let verdicts: Vec<_> = result.test_case_results.iter().map(|r| r.verdict).collect();
+        assert_eq!(verdicts, [TestVerdict::Accepted, TestVerdict::WrongAnswer]);
+    }
+
+    #[test]
+    fn subtasks_score_their_weakest_case() {
+        let case: TestCase = serde_json::from_value(json!({ "input": "", "expected_output": "", "is_hidden": false })).unwrap();
+        let results: Vec<TestCaseResult> = [(true, 1.0), (false, 0.5), (false, 0.25)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (passed, points))| TestCaseResult { passed, points, ..Judge::skipped_result(i, &case) })
+            .collect();
+        let subtasks = [
+            Subtask { test_cases: vec![0], points: 40.0 },
+            Subtask { test_cases: vec![0, 1, 2], points: 60.0 },
+        ];
+        let scored = Judge::score_subtasks(&subtasks, &results);
+        assert!(scored[0].passed);
+        assert_eq!(scored[0].points, 40.0);
+        assert!(!scored[1].passed);
+        assert_eq!(scored[1].points, 15.0);
+    }
+
+    #[test]
+    fn gc_applies_the_configured_run_artifact_window() {
+        let (runs, cache) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let older = run_artifact_aged(runs.path(), Duration::from_secs(90));
+        let newer = run_artifact_aged(runs.path(), Duration::from_secs(30));
+        let policy = RetentionPolicy {
+            run_artifacts: Duration::from_secs(60),
+            cache_entries: Duration::from_secs(3600),
+            debug_binaries: Duration::MAX,
+        };
+        let judge = Judge::new()
+            .unwrap()
+            .with_cache(CompileCache::new(cache.path(), u64::MAX))
+            .with_retention(policy)
+            .with_run_artifact_dir(runs.path());
+
+        judge.gc(&judge.retention());
+        assert!(!older.exists());
+        assert!(newer.exists());
+    }
+}
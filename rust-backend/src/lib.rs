@@ -1,10 +1,16 @@
+pub mod cache;
+pub mod checker;
+pub mod comparator;
 pub mod compiler;
+pub mod diagnostics;
+pub mod diff;
 pub mod executor;
 pub mod judge;
 pub mod sandbox;
 pub mod timer;
 pub mod types;
 pub mod interactive;
+pub mod interactor;
 
 pub use judge::Judge;
 pub use types::*;
@@ -1,7 +1,16 @@
-use dsa_judge::{Judge, JudgeRequest, Problem, TestCase, Difficulty, CodeFile};
-use serde_json;
+use dsa_judge::{Judge, JudgeRequest, Problem, TestCase, Difficulty, CodeFile, ErrorCode, Language, Session, SessionEvent};
+use dsa_judge::compiler::Toolchain;
+use dsa_judge::judge::RetentionPolicy;
+use std::collections::HashMap;
 use std::env;
-use std::io::{self, BufRead, Write};
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::{AbortHandle, JoinSet};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -41,27 +50,70 @@ int main() {
         description: "Read a number and output its double".to_string(),
         difficulty: Difficulty::Easy,
         time_limit: 1000, // 1 second
+        time_limit_mode: Default::default(),
+        soft_time_limit_ms: None,
         memory_limit: 64, // 64 MB
+        memory_sampling: Default::default(),
+        io_mode: Default::default(),
+        output_limit_bytes: None,
         test_cases: vec![
             TestCase {
-                input: "5\n".to_string(),
+                input: "5\n".into(),
                 expected_output: "10\n".to_string(),
+                accepted_outputs: Vec::new(),
+                expected_regex: None,
                 is_hidden: false,
+                weight: None,
+                time_limit_ms: None,
+                memory_limit_mb: None,
+                seed: None,
+                encoding: Default::default(),
             },
             TestCase {
-                input: "10\n".to_string(),
+                input: "10\n".into(),
                 expected_output: "20\n".to_string(),
+                accepted_outputs: Vec::new(),
+                expected_regex: None,
                 is_hidden: false,
+                weight: None,
+                time_limit_ms: None,
+                memory_limit_mb: None,
+                seed: None,
+                encoding: Default::default(),
             },
         ],
         tags: vec!["basic".to_string(), "math".to_string()],
+        checker: None,
+        subtasks: Vec::new(),
+        process_limit: None,
+        idle_limit_ms: None,
+        allow_network: false,
+        args: Vec::new(),
+        env: Vec::new(),
+        interactor: None,
     };
 
     let request = JudgeRequest {
         code: example_code.to_string(),
         problem: example_problem,
-        language: "c".to_string(),
+        language: Language::C,
         normalization: Default::default(),
+        files: None,
+        extra_headers: Vec::new(),
+        stop_on_first_failure: false,
+        max_parallel_tests: None,
+        max_diff_lines: None,
+        max_compile_output_lines: None,
+        compile_timeout_ms: None,
+        max_source_bytes: None,
+        compile_only: false,
+        reveal_hidden: false,
+        hidden_verdict_only: false,
+        stream: false,
+        progress: false,
+        structured_diagnostics: false,
+        total_time_limit_ms: None,
+        compile_options: Default::default(),
     };
 
     let judge = Judge::new()?;
@@ -79,13 +131,33 @@ int main() {
 enum StdioRequest {
     #[serde(rename = "ping")] Ping { id: Option<String> },
     #[serde(rename = "version")] Version { id: Option<String> },
-    #[serde(rename = "env_check")] EnvCheck { id: Option<String> },
-    #[serde(rename = "judge")] Judge { id: Option<String>, request: dsa_judge::JudgeRequest },
+    #[serde(rename = "env_check")] EnvCheck { id: Option<String>, toolchain: Option<Toolchain> },
+    /// Cache size, cached binaries, and in-flight/total judge counts
+    #[serde(rename = "stats")] Stats { id: Option<String> },
+    /// Delete stale run binaries, debug binaries and cache entries; ages default to `Judge::retention()`
+    #[serde(rename = "gc")] Gc { id: Option<String>, run_artifact_max_age_secs: Option<u64>, cache_max_age_secs: Option<u64>, debug_binary_max_age_secs: Option<u64> },
+    /// Compile cache location, entry count, size and oldest/newest last use
+    #[serde(rename = "cache_stats")] CacheStats { id: Option<String> },
+    /// Delete every compile cache entry not in use; replies with the number removed
+    #[serde(rename = "clear_cache")] ClearCache { id: Option<String> },
+    #[serde(rename = "judge")] Judge { id: Option<String>, request: Box<dsa_judge::JudgeRequest> },
+    /// Judge several submissions, `workers` (default 1) at a time; `progress` emits an event per finished submission
+    #[serde(rename = "judge_batch")] JudgeBatch { id: Option<String>, requests: Vec<dsa_judge::JudgeRequest>, workers: Option<usize>, #[serde(default)] progress: bool },
+    /// Abort the in-flight judge or execute request with this id
+    #[serde(rename = "cancel")] Cancel { id: String },
+    /// Run a binary built by `execute`, streaming its output as `session_output` events
+    #[serde(rename = "start_session")] StartSession { id: Option<String>, executable_path: String },
+    /// Send text to a session's stdin; `eof` closes stdin afterwards
+    #[serde(rename = "session_input")] SessionInput { id: Option<String>, session_id: String, #[serde(default)] data: String, #[serde(default)] eof: bool },
+    /// Kill a session's program and forget it
+    #[serde(rename = "session_close")] SessionClose { id: Option<String>, session_id: String },
     #[serde(rename = "execute")] Execute { 
         id: Option<String>, 
         code: Option<String>,
-        language: String,
+        language: Language,
         files: Option<Vec<CodeFile>>,
+        /// Cut the compiler error to this many lines
+        max_compile_output_lines: Option<usize>,
     },
 }
 
@@ -95,6 +167,115 @@ struct StdioResponse<T> {
     success: bool,
     data: Option<T>,
     error: Option<String>,
+    /// `InternalError` whenever `success` is false, `InvalidRequest` for a refused request id;
+    /// judge outcomes carry their own code in `data`
+    error_code: Option<ErrorCode>,
+}
+
+/// Outcome of one submission in a `judge_batch`, keyed by its position in `requests`
+#[derive(serde::Serialize)]
+struct BatchItem {
+    index: usize,
+    success: bool,
+    data: Option<dsa_judge::JudgeResponse>,
+    error: Option<String>,
+    error_code: Option<ErrorCode>,
+}
+
+/// Intermediate message for a request that is still running
+#[derive(serde::Serialize)]
+struct StdioEvent<'a, T> {
+    id: Option<&'a str>,
+    event: &'static str,
+    data: T,
+}
+
+/// How far a long-running request has got, for progress bars
+#[derive(serde::Serialize)]
+struct ProgressEvent<'a> {
+    event: &'static str,
+    id: Option<&'a str>,
+    completed: usize,
+    total: usize,
+}
+
+impl<'a> ProgressEvent<'a> {
+    fn new(id: Option<&'a str>, completed: usize, total: usize) -> Self {
+        Self { event: "progress", id, completed, total }
+    }
+}
+
+/// Write one line of output; judge tasks finish concurrently, so each line is written under the stdout lock
+fn emit<T: serde::Serialize>(resp: &T) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", serde_json::to_string(resp).unwrap())?;
+    stdout.flush()
+}
+
+/// Judge and execute requests currently running, by request id
+type InFlight = Arc<Mutex<HashMap<String, AbortHandle>>>;
+
+/// Open interactive sessions by session id, each holding a concurrency slot until closed or exited
+type Sessions = Arc<Mutex<HashMap<String, (Session, OwnedSemaphorePermit)>>>;
+
+/// Judge/execute operations allowed at once, from `DSA_JUDGE_MAX_CONCURRENT` (default: CPU count)
+fn max_concurrent() -> usize {
+    env::var("DSA_JUDGE_MAX_CONCURRENT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Period of the background `gc`, from `DSA_JUDGE_GC_INTERVAL_SECS` (off when unset)
+fn gc_interval() -> Option<Duration> {
+    env::var("DSA_JUDGE_GC_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// A request that couldn't be carried out, with the reason in `error`
+fn failed(id: Option<String>, error: String) -> StdioResponse<serde_json::Value> {
+    StdioResponse { id, success: false, data: None, error: Some(error), error_code: Some(ErrorCode::InternalError) }
+}
+
+/// Failure for a request refused before it started, e.g. for reusing an id still in flight
+fn rejected(id: Option<String>, error: String) -> StdioResponse<serde_json::Value> {
+    StdioResponse { id, success: false, data: None, error: Some(error), error_code: Some(ErrorCode::InvalidRequest) }
+}
+
+/// Bare success, or `failed` with `error` if there is one
+fn outcome(id: Option<String>, error: Option<String>) -> StdioResponse<serde_json::Value> {
+    match error {
+        Some(error) => failed(id, error),
+        None => StdioResponse { id, success: true, data: None, error: None, error_code: None },
+    }
+}
+
+/// Error sent back when every slot is taken; the client is expected to retry later
+fn busy(id: Option<String>, limit: usize) -> StdioResponse<serde_json::Value> {
+    let error = format!("busy: {} operations already running", limit);
+    failed(id, error)
+}
+
+/// Spawn `task` and register it under `id` in the same critical section, so the task can't
+/// finish and unregister before it is registered. A request whose id is still in flight is
+/// refused instead, since it would take over the other one's cancel handle
+fn spawn_tracked<F>(tasks: &mut JoinSet<()>, in_flight: &InFlight, id: Option<&String>, task: F) -> Result<(), String>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let mut running = in_flight.lock().unwrap();
+    if let Some(id) = id.filter(|id| running.contains_key(*id)) {
+        return Err(format!("A request with id {} is already in flight", id));
+    }
+    let handle = tasks.spawn(task);
+    if let Some(id) = id {
+        running.insert(id.clone(), handle);
+    }
+    Ok(())
 }
 
 async fn run_stdio() -> Result<(), Box<dyn std::error::Error>> {
@@ -104,98 +285,264 @@ async fn run_stdio() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    let judge = Judge::new()?;
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-    let mut lines = stdin.lock().lines();
-
-    while let Some(line) = lines.next() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => break,
-        };
+    let judge = Arc::new(Judge::new()?);
+    if let Some(period) = gc_interval() {
+        let judge = Arc::clone(&judge);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(period);
+            loop {
+                ticks.tick().await;
+                let judge = Arc::clone(&judge);
+                let _ = tokio::task::spawn_blocking(move || judge.gc(&judge.retention())).await;
+            }
+        });
+    }
+    let in_flight: InFlight = Arc::default();
+    // Control messages (ping/version/env_check/stats/cancel) don't take a slot, so health checks stay responsive
+    let limit = max_concurrent();
+    let slots = Arc::new(Semaphore::new(limit));
+    let sessions: Sessions = Arc::default();
+    let mut next_session = 0u64;
+    let mut tasks = JoinSet::new();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        // Drop handles of judge tasks that have already finished
+        while tasks.try_join_next().is_some() {}
         if line.trim().is_empty() { continue; }
         let parsed: Result<StdioRequest, _> = serde_json::from_str(&line);
         match parsed {
             Ok(StdioRequest::Ping { id }) => {
-                let resp = StdioResponse { id, success: true, data: Some("pong".to_string()), error: None };
-                writeln!(stdout, "{}", serde_json::to_string(&resp).unwrap())?;
-                stdout.flush()?;
+                emit(&StdioResponse { id, success: true, data: Some("pong".to_string()), error: None, error_code: None })?;
             }
             Ok(StdioRequest::Version { id }) => {
                 let v = env!("CARGO_PKG_VERSION").to_string();
-                let resp = StdioResponse { id, success: true, data: Some(v), error: None };
-                writeln!(stdout, "{}", serde_json::to_string(&resp).unwrap())?;
-                stdout.flush()?;
+                emit(&StdioResponse { id, success: true, data: Some(v), error: None, error_code: None })?;
+            }
+            Ok(StdioRequest::EnvCheck { id, toolchain }) => {
+                let result = match toolchain {
+                    Some(toolchain) => dsa_judge::Judge::check_toolchain(toolchain),
+                    None => dsa_judge::Judge::check_environment(),
+                };
+                emit(&outcome(id, result.err().map(|e| e.to_string())))?;
+            }
+            Ok(StdioRequest::Stats { id }) => {
+                emit(&StdioResponse { id, success: true, data: Some(judge.stats()), error: None, error_code: None })?;
+            }
+            Ok(StdioRequest::Gc { id, run_artifact_max_age_secs, cache_max_age_secs, debug_binary_max_age_secs }) => {
+                let defaults = judge.retention();
+                let policy = RetentionPolicy {
+                    run_artifacts: run_artifact_max_age_secs.map_or(defaults.run_artifacts, Duration::from_secs),
+                    cache_entries: cache_max_age_secs.map_or(defaults.cache_entries, Duration::from_secs),
+                    debug_binaries: debug_binary_max_age_secs.map_or(defaults.debug_binaries, Duration::from_secs),
+                };
+                let judge = Arc::clone(&judge);
+                let report = tokio::task::spawn_blocking(move || judge.gc(&policy)).await?;
+                emit(&StdioResponse { id, success: true, data: Some(report), error: None, error_code: None })?;
+            }
+            Ok(StdioRequest::CacheStats { id }) => {
+                let judge = Arc::clone(&judge);
+                let stats = tokio::task::spawn_blocking(move || judge.cache_stats()).await?;
+                emit(&StdioResponse { id, success: true, data: Some(stats), error: None, error_code: None })?;
             }
-            Ok(StdioRequest::EnvCheck { id }) => {
-                let result = dsa_judge::Judge::check_environment();
-                let (success, err) = match result { Ok(_) => (true, None), Err(e) => (false, Some(e.to_string())) };
-                let resp = StdioResponse::<String> { id, success, data: None, error: err };
-                writeln!(stdout, "{}", serde_json::to_string(&resp).unwrap())?;
-                stdout.flush()?;
+            Ok(StdioRequest::ClearCache { id }) => {
+                let judge = Arc::clone(&judge);
+                match tokio::task::spawn_blocking(move || judge.clear_cache()).await? {
+                    Ok(removed) => emit(&StdioResponse { id, success: true, data: Some(removed), error: None, error_code: None })?,
+                    Err(e) => emit(&failed(id, format!("{:#}", e)))?,
+                }
             }
             Ok(StdioRequest::Judge { id, request }) => {
-                let resp = judge.judge(request).await;
-                match resp {
-                    Ok(val) => {
-                        let wrap = StdioResponse { id, success: true, data: Some(val), error: None };
-                        writeln!(stdout, "{}", serde_json::to_string(&wrap).unwrap())?;
+                let Ok(permit) = Arc::clone(&slots).try_acquire_owned() else {
+                    emit(&busy(id, limit))?;
+                    continue;
+                };
+                // Judged in the background so the loop keeps reading (and can cancel it)
+                let (judge, in_flight_task) = (Arc::clone(&judge), Arc::clone(&in_flight));
+                let task_id = id.clone();
+                let spawned = spawn_tracked(&mut tasks, &in_flight, id.as_ref(), async move {
+                    let resp = if request.stream || request.progress {
+                        let (stream, progress) = (request.stream, request.progress);
+                        let (total, completed) = (request.problem.test_cases.len(), AtomicUsize::new(0));
+                        judge
+                            .judge_with_progress(*request, |result| {
+                                if stream {
+                                    let _ = emit(&StdioEvent { id: task_id.as_deref(), event: "test_result", data: result });
+                                }
+                                if progress {
+                                    let completed = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                                    let _ = emit(&ProgressEvent::new(task_id.as_deref(), completed, total));
+                                }
+                            })
+                            .await
+                    } else {
+                        judge.judge(*request).await
+                    };
+                    drop(permit);
+                    if let Some(task_id) = &task_id {
+                        in_flight_task.lock().unwrap().remove(task_id);
+                    }
+                    let _ = match resp {
+                        Ok(val) => emit(&StdioResponse { id: task_id, success: true, data: Some(val), error: None, error_code: None }),
+                        Err(e) => emit(&failed(task_id, e.to_string())),
+                    };
+                });
+                if let Err(error) = spawned {
+                    emit(&rejected(id, error))?;
+                }
+            }
+            Ok(StdioRequest::JudgeBatch { id, requests, workers, progress }) => {
+                // A batch holds one slot per worker (capped at the limit, so it can start at all)
+                let workers = workers.unwrap_or(1).clamp(1, limit);
+                let Ok(permit) = Arc::clone(&slots).try_acquire_many_owned(workers as u32) else {
+                    emit(&busy(id, limit))?;
+                    continue;
+                };
+                let (judge, in_flight_task) = (Arc::clone(&judge), Arc::clone(&in_flight));
+                let task_id = id.clone();
+                let spawned = spawn_tracked(&mut tasks, &in_flight, id.as_ref(), async move {
+                    let (total, completed) = (requests.len(), AtomicUsize::new(0));
+                    let responses = judge
+                        .judge_batch_with_progress(requests, workers, |_| {
+                            if progress {
+                                let completed = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                                let _ = emit(&ProgressEvent::new(task_id.as_deref(), completed, total));
+                            }
+                        })
+                        .await;
+                    drop(permit);
+                    if let Some(task_id) = &task_id {
+                        in_flight_task.lock().unwrap().remove(task_id);
+                    }
+                    let items: Vec<BatchItem> = responses
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, resp)| match resp {
+                            Ok(val) => BatchItem { index, success: true, data: Some(val), error: None, error_code: None },
+                            Err(e) => BatchItem { index, success: false, data: None, error: Some(e.to_string()), error_code: Some(ErrorCode::InternalError) },
+                        })
+                        .collect();
+                    let _ = emit(&StdioResponse { id: task_id, success: true, data: Some(items), error: None, error_code: None });
+                });
+                if let Err(error) = spawned {
+                    emit(&rejected(id, error))?;
+                }
+            }
+            Ok(StdioRequest::Cancel { id }) => {
+                let handle = in_flight.lock().unwrap().remove(&id);
+                let resp = match handle {
+                    Some(handle) => {
+                        handle.abort();
+                        failed(Some(id), "Cancelled".to_string())
+                    }
+                    None => {
+                        let error = format!("No request in flight with id {}", id);
+                        failed(Some(id), error)
+                    }
+                };
+                emit(&resp)?;
+            }
+            Ok(StdioRequest::StartSession { id, executable_path }) => {
+                let Ok(permit) = Arc::clone(&slots).try_acquire_owned() else {
+                    emit(&busy(id, limit))?;
+                    continue;
+                };
+                next_session += 1;
+                let session_id = format!("session-{}", next_session);
+                let event_id = session_id.clone();
+                let owner = Arc::clone(&sessions);
+                let started = Session::start(&executable_path, move |event| {
+                    // The program is gone: forget the session so its slot is free before the client hears
+                    if matches!(event, SessionEvent::Exit { .. }) {
+                        owner.lock().unwrap().remove(&event_id);
                     }
-                    Err(e) => {
-                        let wrap: StdioResponse::<serde_json::Value> = StdioResponse { id, success: false, data: None, error: Some(e.to_string()) };
-                        writeln!(stdout, "{}", serde_json::to_string(&wrap).unwrap())?;
+                    let _ = emit(&StdioEvent { id: Some(&event_id), event: "session_output", data: event });
+                });
+                match started {
+                    Ok(session) => {
+                        let mut open = sessions.lock().unwrap();
+                        // A program that already exited had nothing to remove yet
+                        if !session.has_exited() {
+                            open.insert(session_id.clone(), (session, permit));
+                        }
+                        drop(open);
+                        let data = serde_json::json!({ "session_id": session_id });
+                        emit(&StdioResponse { id, success: true, data: Some(data), error: None, error_code: None })?;
                     }
+                    Err(e) => emit(&failed(id, format!("{:#}", e)))?,
                 }
-                stdout.flush()?;
             }
-            Ok(StdioRequest::Execute { id, code, language, files }) => {
+            Ok(StdioRequest::SessionInput { id, session_id, data, eof }) => {
+                let result = match sessions.lock().unwrap().get_mut(&session_id) {
+                    Some((session, _)) => {
+                        let written = if data.is_empty() { Ok(()) } else { session.write(data) };
+                        if eof {
+                            session.close_stdin();
+                        }
+                        written
+                    }
+                    None => Err(anyhow::anyhow!("No session with id {}", session_id)),
+                };
+                emit(&outcome(id, result.err().map(|e| e.to_string())))?;
+            }
+            Ok(StdioRequest::SessionClose { id, session_id }) => {
+                // Dropping the session kills the program and frees its slot
+                let error = match sessions.lock().unwrap().remove(&session_id) {
+                    Some(_) => None,
+                    None => Some(format!("No session with id {}", session_id)),
+                };
+                emit(&outcome(id, error))?;
+            }
+            Ok(StdioRequest::Execute { id, code, language, files, max_compile_output_lines }) => {
+                let Ok(permit) = Arc::clone(&slots).try_acquire_owned() else {
+                    emit(&busy(id, limit))?;
+                    continue;
+                };
                 // Prepare files for compilation
                 let compile_files = if let Some(fs) = files {
                     fs
                 } else if let Some(c) = code {
                     // Single file mode
-                    let filename = if language == "cpp" { "main.cpp" } else { "main.c" };
+                    let filename = if language == Language::Cpp { "main.cpp" } else { "main.c" };
                     vec![CodeFile { filename: filename.to_string(), content: c }]
                 } else {
                     // Error: need either files or code
-                    let wrap: StdioResponse::<serde_json::Value> = StdioResponse { 
-                        id, 
-                        success: false, 
-                        data: None, 
-                        error: Some("Either 'code' or 'files' must be provided".to_string()) 
-                    };
-                    writeln!(stdout, "{}", serde_json::to_string(&wrap).unwrap())?;
-                    stdout.flush()?;
+                    emit(&failed(id, "Either 'code' or 'files' must be provided".to_string()))?;
                     continue;
                 };
                 
-                let compile_result = dsa_judge::interactive::compile_files(compile_files, &language).await;
-                
-                match compile_result {
-                    Ok(result) => {
-                        let wrap = StdioResponse { id, success: true, data: Some(result), error: None };
-                        writeln!(stdout, "{}", serde_json::to_string(&wrap).unwrap())?;
-                    }
-                    Err(e) => {
-                        let wrap: StdioResponse::<serde_json::Value> = StdioResponse { 
-                            id, 
-                            success: false, 
-                            data: None, 
-                            error: Some(e.to_string()) 
-                        };
-                        writeln!(stdout, "{}", serde_json::to_string(&wrap).unwrap())?;
+                // Compiled in the background, like judge requests, so the loop keeps reading
+                let (judge, in_flight_task) = (Arc::clone(&judge), Arc::clone(&in_flight));
+                let task_id = id.clone();
+                let spawned = spawn_tracked(&mut tasks, &in_flight, id.as_ref(), async move {
+                    let compile_result = judge.compile_files(compile_files, language).await;
+                    drop(permit);
+                    if let Some(task_id) = &task_id {
+                        in_flight_task.lock().unwrap().remove(task_id);
                     }
+                    let _ = match compile_result {
+                        Ok(mut result) => {
+                            if let Some(max) = max_compile_output_lines {
+                                result.truncate_error(max);
+                            }
+                            emit(&StdioResponse { id: task_id, success: true, data: Some(result), error: None, error_code: None })
+                        }
+                        Err(e) => emit(&failed(task_id, e.to_string())),
+                    };
+                });
+                if let Err(error) = spawned {
+                    emit(&rejected(id, error))?;
                 }
-                stdout.flush()?;
             }
             Err(e) => {
-                let resp = StdioResponse::<String> { id: None, success: false, data: None, error: Some(format!("invalid request: {}", e)) };
-                writeln!(stdout, "{}", serde_json::to_string(&resp).unwrap())?;
-                stdout.flush()?;
+                emit(&failed(None, format!("invalid request: {}", e)))?;
             }
         }
     }
 
+    // Input closed: kill interactive sessions, let outstanding judge requests finish and report
+    sessions.lock().unwrap().clear();
+    while tasks.join_next().await.is_some() {}
+
     Ok(())
 }
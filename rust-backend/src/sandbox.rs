@@ -29,10 +29,56 @@ impl Sandbox {
         Ok(())
     }
 
-    /// Check if the sandbox is properly configured
+    /// Check if the sandbox is properly configured: submissions run in the jail and
+    /// see only their own files and the system runtimes, not the host filesystem
     pub fn is_secure(&self) -> bool {
         self.working_directory.exists() && 
-        self.working_directory.is_dir()
+        self.working_directory.is_dir() &&
+        Self::filesystem_confined()
+    }
+
+    /// Whether submissions can run inside the namespace + seccomp jail on this host
+    pub fn jail_available() -> bool {
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        return linux::jail().is_some();
+        #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        return false;
+    }
+
+    /// Whether jailed submissions also get a private root filesystem on this host
+    pub fn filesystem_confined() -> bool {
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        return linux::jail().is_some_and(|jail| jail.confines_filesystem());
+        #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        return false;
+    }
+
+    /// Run the command inside the jail when the host supports it; otherwise leave it
+    /// unconfined apart from a seccomp filter on `socket`/`connect` when networking is
+    /// off (a warning is printed once when the jail is first found missing).
+    ///
+    /// Inside the jail the child's root is a fresh tmpfs holding read-only copies of the
+    /// system directories, its program's runtime and `exposed` (files or directories),
+    /// a private `/tmp`, and its working directory (writable, `/tmp` when unset).
+    pub(crate) fn confine(cmd: &mut tokio::process::Command, allow_network: bool, exposed: &[&Path]) {
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        if let Some(jail) = linux::jail() {
+            let view = jail
+                .confines_filesystem()
+                .then(|| linux::View::new(cmd.as_std(), exposed));
+            // SAFETY: `enter` only issues raw syscalls on data prepared before the fork
+            unsafe {
+                cmd.pre_exec(move || jail.enter(allow_network, view.as_ref()));
+            }
+        } else if !allow_network {
+            let filter = linux::network_filter();
+            // SAFETY: as above; the filter is a static built before the fork
+            unsafe {
+                cmd.pre_exec(move || linux::install_filter(filter));
+            }
+        }
+        #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        let _ = (cmd, allow_network, exposed);
     }
 
     /// Get the working directory path
@@ -55,3 +101,566 @@ impl Drop for Sandbox {
         let _ = self.cleanup();
     }
 }
+
+/// Linux jail: fresh user/mount/net/ipc/uts namespaces plus a seccomp-bpf
+/// syscall allowlist, entered by the child between fork and exec
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod linux {
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, OnceLock};
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    /// Syscalls needed by compiled programs and the Python/Java runtimes for ordinary
+    /// stdin/stdout work; anything else (sockets, mounts, ptrace, ...) fails with EPERM
+    const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+        // I/O and files
+        libc::SYS_read, libc::SYS_write, libc::SYS_readv, libc::SYS_writev,
+        libc::SYS_pread64, libc::SYS_pwrite64, libc::SYS_lseek, libc::SYS_close,
+        libc::SYS_openat, libc::SYS_newfstatat, libc::SYS_fstat, libc::SYS_statx,
+        libc::SYS_faccessat, libc::SYS_faccessat2, libc::SYS_readlinkat, libc::SYS_getdents64,
+        libc::SYS_fcntl, libc::SYS_dup, libc::SYS_dup3, libc::SYS_ioctl, libc::SYS_pipe2,
+        libc::SYS_ftruncate, libc::SYS_fadvise64, libc::SYS_getcwd, libc::SYS_chdir, libc::SYS_fchdir, libc::SYS_flock,
+        libc::SYS_mkdirat, libc::SYS_unlinkat, libc::SYS_umask, libc::SYS_fsync,
+        libc::SYS_ppoll, libc::SYS_pselect6, libc::SYS_epoll_create1, libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait, libc::SYS_eventfd2, libc::SYS_statfs, libc::SYS_fstatfs,
+        // Memory
+        libc::SYS_brk, libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mremap,
+        libc::SYS_mprotect, libc::SYS_madvise, libc::SYS_membarrier,
+        // Threads, signals and process lifetime
+        libc::SYS_clone, libc::SYS_clone3, libc::SYS_execve, libc::SYS_exit, libc::SYS_exit_group,
+        libc::SYS_wait4, libc::SYS_futex, libc::SYS_set_robust_list, libc::SYS_get_robust_list,
+        libc::SYS_set_tid_address, libc::SYS_rseq, libc::SYS_rt_sigaction, libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn, libc::SYS_sigaltstack,
+        libc::SYS_restart_syscall, libc::SYS_prctl, libc::SYS_prlimit64, libc::SYS_getrlimit,
+        libc::SYS_sched_yield, libc::SYS_sched_getaffinity, libc::SYS_sched_getparam,
+        libc::SYS_sched_getscheduler,
+        // Identity, clocks and system info
+        libc::SYS_getpid, libc::SYS_gettid, libc::SYS_getppid, libc::SYS_getuid, libc::SYS_geteuid,
+        libc::SYS_getgid, libc::SYS_getegid, libc::SYS_getresuid, libc::SYS_getresgid,
+        libc::SYS_getrandom, libc::SYS_clock_gettime, libc::SYS_clock_getres,
+        libc::SYS_clock_nanosleep, libc::SYS_nanosleep, libc::SYS_gettimeofday, libc::SYS_times,
+        libc::SYS_getrusage, libc::SYS_sysinfo, libc::SYS_uname,
+    ];
+
+    /// Extra syscalls allowed when the problem permits networking
+    const NETWORK_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_socket, libc::SYS_socketpair, libc::SYS_connect, libc::SYS_bind, libc::SYS_listen,
+        libc::SYS_accept4, libc::SYS_sendto, libc::SYS_recvfrom, libc::SYS_sendmsg, libc::SYS_recvmsg,
+        libc::SYS_shutdown, libc::SYS_getsockname, libc::SYS_getpeername, libc::SYS_setsockopt,
+        libc::SYS_getsockopt,
+    ];
+
+    /// Syscalls rejected by the network-only filter used when the full jail is unavailable
+    const BLOCKED_NETWORK_SYSCALLS: &[libc::c_long] = &[libc::SYS_socket, libc::SYS_connect];
+
+    /// Pre-`*at` variants that only exist on x86_64
+    #[cfg(target_arch = "x86_64")]
+    const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_open, libc::SYS_stat, libc::SYS_lstat, libc::SYS_access, libc::SYS_readlink,
+        libc::SYS_poll, libc::SYS_select, libc::SYS_pipe, libc::SYS_dup2, libc::SYS_getdents,
+        libc::SYS_arch_prctl, libc::SYS_epoll_wait, libc::SYS_epoll_create, libc::SYS_time,
+        libc::SYS_mkdir, libc::SYS_unlink,
+        // Shells such as dash start commands with vfork
+        libc::SYS_fork, libc::SYS_vfork,
+    ];
+    #[cfg(not(target_arch = "x86_64"))]
+    const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[];
+
+    fn stmt(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt, jf, k }
+    }
+
+    const RET_EPERM: u32 = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    /// Stands for the jailed process's pid in a filter template until `with_pid` fills it in
+    const PID_PLACEHOLDER: u32 = 0x7fff_fff0;
+    /// `-PID_PLACEHOLDER`, i.e. the process's own group in `kill(-pgid, sig)`
+    const GROUP_PLACEHOLDER: u32 = PID_PLACEHOLDER.wrapping_neg();
+    /// Upper bound on a filter's length, so `with_pid` can work on the stack
+    const MAX_FILTER_LEN: usize = 512;
+
+    /// Signal syscalls limited to the process itself: without a pid namespace and with the
+    /// same uid, an unrestricted `kill(-1, SIGKILL)` would take down the judge. `kill` may
+    /// target pid 0, the process or its group (it leads its own); `tgkill` its own threads.
+    /// Expects `seccomp_data.nr` loaded and leaves it loaded for what follows.
+    fn signal_rules() -> Vec<libc::sock_filter> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+        const ARG0: u32 = 16; // low half of seccomp_data.args[0]; pid_t is 32 bits
+        vec![
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_kill as u32, 0, 6),
+            stmt(BPF_LD | BPF_W | BPF_ABS, ARG0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, 0, 3, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, PID_PLACEHOLDER, 2, 0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, GROUP_PLACEHOLDER, 1, 0),
+            stmt(BPF_RET | BPF_K, RET_EPERM),
+            stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, libc::SYS_tgkill as u32, 0, 4),
+            stmt(BPF_LD | BPF_W | BPF_ABS, ARG0),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, PID_PLACEHOLDER, 1, 0),
+            stmt(BPF_RET | BPF_K, RET_EPERM),
+            stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW),
+        ]
+    }
+
+    /// Copy of `template` with the pid placeholders replaced by the caller's pid; runs in
+    /// the forked child, so it fills `buf` instead of allocating
+    fn with_pid<'b>(
+        template: &[libc::sock_filter],
+        buf: &'b mut [libc::sock_filter; MAX_FILTER_LEN],
+    ) -> &'b [libc::sock_filter] {
+        // SAFETY: getpid cannot fail
+        let pid = unsafe { libc::getpid() } as u32;
+        let filter = &mut buf[..template.len()];
+        filter.copy_from_slice(template);
+        for instruction in filter.iter_mut() {
+            if instruction.code == (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16 {
+                if instruction.k == PID_PLACEHOLDER {
+                    instruction.k = pid;
+                } else if instruction.k == GROUP_PLACEHOLDER {
+                    instruction.k = pid.wrapping_neg();
+                }
+            }
+        }
+        filter
+    }
+
+    /// BPF program: kill on a foreign architecture, run `guards` (which return for the
+    /// syscalls they handle), then return `on_match` for the listed syscalls and
+    /// `otherwise` for the rest
+    fn seccomp_filter<'a>(
+        guards: Vec<libc::sock_filter>,
+        syscalls: impl Iterator<Item = &'a libc::c_long>,
+        on_match: u32,
+        otherwise: u32,
+    ) -> Vec<libc::sock_filter> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+        let mut filter = vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, 4), // seccomp_data.arch
+            jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+            stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD | BPF_W | BPF_ABS, 0), // seccomp_data.nr
+        ];
+        filter.extend(guards);
+        for &nr in syscalls {
+            filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr as u32, 0, 1));
+            filter.push(stmt(BPF_RET | BPF_K, on_match));
+        }
+        filter.push(stmt(BPF_RET | BPF_K, otherwise));
+        filter
+    }
+
+    /// Install a seccomp filter on the calling process; runs in the forked child
+    pub fn install_filter(filter: &[libc::sock_filter]) -> std::io::Result<()> {
+        // SAFETY: prctl on a filter that outlives the call
+        unsafe {
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            let program = libc::sock_fprog {
+                len: filter.len() as u16,
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            };
+            check(libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program as *const _))
+        }
+    }
+
+    /// Filter that only blocks opening network connections, for hosts without the jail
+    pub fn network_filter() -> &'static [libc::sock_filter] {
+        static FILTER: OnceLock<Vec<libc::sock_filter>> = OnceLock::new();
+        FILTER.get_or_init(|| seccomp_filter(Vec::new(), BLOCKED_NETWORK_SYSCALLS.iter(), RET_EPERM, libc::SECCOMP_RET_ALLOW))
+    }
+
+    /// Everything the child needs, prepared up front so `enter` never allocates
+    pub struct Jail {
+        filter: Vec<libc::sock_filter>,
+        network_filter: Vec<libc::sock_filter>,
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        filesystem: bool,
+    }
+
+    fn check(ret: libc::c_int) -> std::io::Result<()> {
+        if ret == -1 { Err(std::io::Error::last_os_error()) } else { Ok(()) }
+    }
+
+    /// Best-effort write of a small /proc file using raw syscalls
+    fn write_proc(path: &CStr, contents: &[u8]) {
+        // SAFETY: raw open/write/close on a NUL-terminated path
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd >= 0 {
+                libc::write(fd, contents.as_ptr().cast(), contents.len());
+                libc::close(fd);
+            }
+        }
+    }
+
+    /// Host paths every jailed program may read: the loader, libc, system runtimes and
+    /// the harmless device nodes. `/dev` itself stays out, since its `shm`, `mqueue` and
+    /// `pts` mounts would let runs share (and leave behind) writable memory
+    const SYSTEM_PATHS: &[&str] = &[
+        "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32", "/etc",
+        "/dev/null", "/dev/zero", "/dev/full", "/dev/random", "/dev/urandom",
+        "/dev/fd", "/dev/stdin", "/dev/stdout", "/dev/stderr",
+    ];
+
+    /// Host-wide `/proc` files runtimes read to size themselves. The rest of the host
+    /// procfs stays out: through `/proc/<pid>` it reaches the judge's root, cwd and
+    /// environment; the child only gets its own entry as `/proc/self`.
+    const PROC_FILES: &[&str] = &["/proc/cpuinfo", "/proc/meminfo", "/proc/stat", "/proc/loadavg"];
+
+    /// One step of building the private root, applied in order by the child
+    #[derive(Clone)]
+    enum Step {
+        Mkdir(CString),
+        Touch(CString),
+        Symlink { target: CString, link: CString },
+        Bind { source: CString, target: CString, writable: bool },
+        /// A mount the recursive bind above it brought along, made read-only like its parent
+        ReadOnly(CString),
+        Tmpfs(CString),
+    }
+
+    fn c_path(path: &Path) -> CString {
+        CString::new(path.as_os_str().as_bytes()).unwrap_or_default()
+    }
+
+    /// The filesystem a jailed child sees: a tmpfs root mounted over an empty host
+    /// directory, holding bind mounts of the paths it may use, then pivoted into
+    pub struct View {
+        root: CString,
+        steps: Vec<Step>,
+        cwd: CString,
+    }
+
+    /// Collects the steps for a [`View`], creating each parent directory once
+    #[derive(Clone)]
+    struct Layout {
+        root: PathBuf,
+        steps: Vec<Step>,
+        shown: Vec<PathBuf>,
+        dirs: HashSet<PathBuf>,
+    }
+
+    impl Layout {
+        fn target(&self, path: &Path) -> PathBuf {
+            self.root.join(path.strip_prefix("/").unwrap_or(path))
+        }
+
+        fn mkdirs(&mut self, path: &Path) {
+            let mut ancestors: Vec<&Path> = path.ancestors().filter(|dir| dir.parent().is_some()).collect();
+            ancestors.reverse();
+            for dir in ancestors {
+                if self.dirs.insert(dir.to_path_buf()) {
+                    self.steps.push(Step::Mkdir(c_path(&self.target(dir))));
+                }
+            }
+        }
+
+        /// Bind `path` into the root at the same location; skipped when missing or
+        /// already visible read-only through an earlier entry. Returns whether `path`
+        /// is visible afterwards.
+        fn show(&mut self, path: &Path, writable: bool) -> bool {
+            if !writable && self.shown.iter().any(|shown| path.starts_with(shown)) {
+                return true;
+            }
+            let Ok(meta) = std::fs::symlink_metadata(path) else { return false };
+            if let Some(parent) = path.parent() {
+                self.mkdirs(parent);
+            }
+            let target = self.target(path);
+            if meta.file_type().is_symlink() {
+                let Ok(link) = std::fs::read_link(path) else { return false };
+                self.steps.push(Step::Symlink { target: c_path(&link), link: c_path(&target) });
+            } else {
+                if meta.is_dir() {
+                    self.mkdirs(path);
+                } else {
+                    self.steps.push(Step::Touch(c_path(&target)));
+                }
+                self.steps.push(Step::Bind { source: c_path(path), target: c_path(&target), writable });
+                if !writable && meta.is_dir() {
+                    for mount in submounts(path) {
+                        self.steps.push(Step::ReadOnly(c_path(&self.target(&mount))));
+                    }
+                }
+            }
+            self.shown.push(path.to_path_buf());
+            true
+        }
+
+        /// The part shared by every view, built once: the system paths, the allowed
+        /// `/proc` entries and a private `/tmp`
+        fn base() -> &'static Layout {
+            static BASE: OnceLock<Layout> = OnceLock::new();
+            BASE.get_or_init(|| {
+                let root = std::env::temp_dir().join("dsa_judge_root");
+                let _ = std::fs::create_dir_all(&root);
+                let mut layout = Layout { root, steps: Vec::new(), shown: Vec::new(), dirs: HashSet::new() };
+                for path in SYSTEM_PATHS.iter().chain(PROC_FILES) {
+                    layout.show(Path::new(path), false);
+                }
+                // Mounting resolves `/proc/self` in the child, i.e. to its own entry
+                let own = Path::new("/proc/self");
+                layout.mkdirs(own);
+                layout.steps.push(Step::Bind { source: c_path(own), target: c_path(&layout.target(own)), writable: false });
+                let tmp = Path::new("/tmp");
+                layout.mkdirs(tmp);
+                layout.steps.push(Step::Tmpfs(c_path(&layout.target(tmp))));
+                layout
+            })
+        }
+    }
+
+    /// Mount points strictly below `dir` in the judge's mount table, which a recursive
+    /// bind of `dir` carries into the jail
+    fn submounts(dir: &Path) -> Vec<PathBuf> {
+        let Ok(table) = std::fs::read_to_string("/proc/self/mountinfo") else { return Vec::new() };
+        table
+            .lines()
+            .filter_map(|line| line.split(' ').nth(4))
+            .map(unescape_mount_point)
+            .filter(|point| point != dir && point.starts_with(dir))
+            .collect()
+    }
+
+    /// Undo mountinfo's `\ooo` octal escapes (space, tab, newline, backslash)
+    fn unescape_mount_point(field: &str) -> PathBuf {
+        let bytes = field.as_bytes();
+        let mut path = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+            match octal.and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()) {
+                Some(byte) => {
+                    path.push(byte);
+                    i += 4;
+                }
+                None => {
+                    path.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        PathBuf::from(OsString::from_vec(path))
+    }
+
+    /// Where `program` lives, and whether it was looked up on `PATH`; lookups are
+    /// remembered per name, since the same few interpreters are started for every run
+    fn resolve_program(program: &OsStr) -> Option<(PathBuf, bool)> {
+        static FOUND: OnceLock<Mutex<HashMap<OsString, Option<PathBuf>>>> = OnceLock::new();
+        let path = Path::new(program);
+        if path.components().count() > 1 {
+            return std::path::absolute(path).ok().map(|found| (found, false));
+        }
+        let mut found = FOUND.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+        found
+            .entry(program.to_os_string())
+            .or_insert_with(|| {
+                std::env::split_paths(&std::env::var_os("PATH")?)
+                    .map(|dir| dir.join(path))
+                    .find(|candidate| candidate.is_file())
+                    .and_then(|found| found.canonicalize().ok())
+            })
+            .clone()
+            .map(|found| (found, true))
+    }
+
+    impl View {
+        /// Layout for `cmd`: the system paths, a private `/tmp`, the program (or, for an
+        /// interpreter found on `PATH` outside the system paths, its install prefix),
+        /// `exposed`, all read-only, and the command's working directory, writable
+        pub fn new(cmd: &std::process::Command, exposed: &[&Path]) -> Self {
+            let mut layout = Layout::base().clone();
+            if let Some((program, on_path)) = resolve_program(cmd.get_program()) {
+                let prefix = program.parent().and_then(Path::parent).filter(|prefix| prefix.components().count() > 2);
+                match prefix {
+                    Some(prefix) if on_path => layout.show(prefix, false),
+                    _ => layout.show(&program, false),
+                };
+            }
+            for path in exposed {
+                if let Ok(path) = std::path::absolute(path) {
+                    layout.show(&path, false);
+                }
+            }
+            let cwd = cmd
+                .get_current_dir()
+                .and_then(|dir| std::path::absolute(dir).ok())
+                .filter(|dir| layout.show(dir, true))
+                .unwrap_or_else(|| PathBuf::from("/tmp"));
+            Self { root: c_path(&layout.root), steps: layout.steps, cwd: c_path(&cwd) }
+        }
+
+        /// Build the root and pivot into it; runs in the child after `unshare`
+        unsafe fn enter(&self) -> std::io::Result<()> {
+            const NO_DEVICES: libc::c_ulong = libc::MS_NOSUID | libc::MS_NODEV;
+            check(libc::mount(c"tmpfs".as_ptr(), self.root.as_ptr(), c"tmpfs".as_ptr(), NO_DEVICES, c"mode=755".as_ptr().cast()))?;
+            for step in &self.steps {
+                match step {
+                    Step::Mkdir(path) => {
+                        if libc::mkdir(path.as_ptr(), 0o755) == -1 && *libc::__errno_location() != libc::EEXIST {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    Step::Touch(path) => {
+                        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC, 0o644);
+                        check(fd)?;
+                        libc::close(fd);
+                    }
+                    Step::Symlink { target, link } => check(libc::symlink(target.as_ptr(), link.as_ptr()))?,
+                    Step::Tmpfs(path) => check(libc::mount(
+                        c"tmpfs".as_ptr(),
+                        path.as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        NO_DEVICES,
+                        c"size=64m,mode=1777".as_ptr().cast(),
+                    ))?,
+                    Step::Bind { source, target, writable } => {
+                        check(libc::mount(
+                            source.as_ptr(),
+                            target.as_ptr(),
+                            std::ptr::null(),
+                            libc::MS_BIND | libc::MS_REC,
+                            std::ptr::null(),
+                        ))?;
+                        if !writable {
+                            remount_read_only(target)?;
+                        }
+                    }
+                    Step::ReadOnly(target) => remount_read_only(target)?,
+                }
+            }
+            // Stack the host root under the new one, then detach it
+            check(libc::chdir(self.root.as_ptr()))?;
+            check(libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int)?;
+            check(libc::umount2(c".".as_ptr(), libc::MNT_DETACH))?;
+            check(libc::chdir(self.cwd.as_ptr()))
+        }
+    }
+
+    /// Make a fresh bind mount read-only, keeping the flags the host locked on the
+    /// original mount (a remount dropping them is refused inside a user namespace)
+    unsafe fn remount_read_only(target: &CStr) -> std::io::Result<()> {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        check(libc::statvfs(target.as_ptr(), &mut stat))?;
+        let locked = stat.f_flag
+            & (libc::ST_NOSUID | libc::ST_NODEV | libc::ST_NOEXEC | libc::ST_NOATIME | libc::ST_NODIRATIME | libc::ST_RELATIME);
+        check(libc::mount(
+            std::ptr::null(),
+            target.as_ptr(),
+            std::ptr::null(),
+            libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | locked as libc::c_ulong,
+            std::ptr::null(),
+        ))
+    }
+
+    impl Jail {
+        fn new() -> Self {
+            // SAFETY: getuid/getgid cannot fail
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let jail = Self {
+                filter: seccomp_filter(
+                    signal_rules(),
+                    ALLOWED_SYSCALLS.iter().chain(ALLOWED_LEGACY_SYSCALLS),
+                    libc::SECCOMP_RET_ALLOW,
+                    RET_EPERM,
+                ),
+                network_filter: seccomp_filter(
+                    signal_rules(),
+                    ALLOWED_SYSCALLS.iter().chain(ALLOWED_LEGACY_SYSCALLS).chain(NETWORK_SYSCALLS),
+                    libc::SECCOMP_RET_ALLOW,
+                    RET_EPERM,
+                ),
+                uid_map: format!("{} {} 1", uid, uid).into_bytes(),
+                gid_map: format!("{} {} 1", gid, gid).into_bytes(),
+                filesystem: false,
+            };
+            assert!(jail.network_filter.len() <= MAX_FILTER_LEN, "seccomp filter too long");
+            jail
+        }
+
+        /// Whether children get a private root (see [`View`]) on this host
+        pub fn confines_filesystem(&self) -> bool {
+            self.filesystem
+        }
+
+        /// Confine the calling process; runs in the forked child right before exec.
+        /// Without `allow_network` the child gets an empty network namespace (no
+        /// interfaces up) and no socket syscalls; with a `view` it also swaps its root.
+        pub fn enter(&self, allow_network: bool, view: Option<&View>) -> std::io::Result<()> {
+            // No CLONE_NEWPID: a process that unshares its pid namespace can no longer
+            // create threads, which breaks the JVM and any threaded solution
+            let mut namespaces = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWIPC | libc::CLONE_NEWUTS;
+            if !allow_network {
+                namespaces |= libc::CLONE_NEWNET;
+            }
+            // SAFETY: only async-signal-safe syscalls on memory owned by `self`
+            unsafe {
+                check(libc::unshare(namespaces))?;
+                // Keep our own uid/gid inside the namespace so file permissions behave as before
+                write_proc(c"/proc/self/setgroups", b"deny");
+                write_proc(c"/proc/self/uid_map", &self.uid_map);
+                write_proc(c"/proc/self/gid_map", &self.gid_map);
+                // Stop mount events propagating between the jail and the host
+                libc::mount(
+                    std::ptr::null(),
+                    c"/".as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                );
+                if let Some(view) = view {
+                    view.enter()?;
+                }
+            }
+            let mut buf = [libc::sock_filter { code: 0, jt: 0, jf: 0, k: 0 }; MAX_FILTER_LEN];
+            install_filter(with_pid(if allow_network { &self.network_filter } else { &self.filter }, &mut buf))
+        }
+    }
+
+    /// Enter the jail in a throwaway child to find out whether the host allows it
+    fn probe(jail: &Jail, view: Option<&View>) -> bool {
+        // SAFETY: the child only runs `enter` (raw syscalls) and `_exit`
+        unsafe {
+            match libc::fork() {
+                -1 => false,
+                0 => libc::_exit(if jail.enter(false, view).is_ok() { 0 } else { 1 }),
+                pid => {
+                    let mut status = 0;
+                    libc::waitpid(pid, &mut status, 0) == pid
+                        && libc::WIFEXITED(status)
+                        && libc::WEXITSTATUS(status) == 0
+                }
+            }
+        }
+    }
+
+    /// The host's jail, probed once; `None` when namespaces or seccomp are unavailable
+    pub fn jail() -> Option<&'static Jail> {
+        static JAIL: OnceLock<Option<Jail>> = OnceLock::new();
+        JAIL.get_or_init(|| {
+            let mut jail = Jail::new();
+            if probe(&jail, None) {
+                jail.filesystem = probe(&jail, Some(&View::new(&std::process::Command::new("/bin/true"), &[])));
+                if !jail.filesystem {
+                    eprintln!("Warning: cannot build a private root on this host; jailed submissions can still read host files");
+                }
+                Some(jail)
+            } else {
+                eprintln!("Warning: namespaces/seccomp unavailable on this host; submissions will run without a sandbox");
+                None
+            }
+        })
+        .as_ref()
+    }
+}
@@ -1,27 +1,214 @@
+use crate::compiler::CompileOptions;
+use crate::diagnostics::Diagnostic;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OverallStatus {
     Ok,
     CompileError,
+    /// The compiler itself ran past `compile_timeout_ms`
+    CompileTimeout,
+    /// The compiler hit `compile_memory_limit_mb` or `compile_cpu_limit_secs`
+    CompileResourceLimitExceeded,
     RuntimeError,
     Timeout,
+    /// A run neither printed nor used CPU for `idle_limit_ms`
+    IdlenessLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
+    /// Judging ran past `total_time_limit_ms`; the remaining cases were skipped
+    TotalTimeLimitExceeded,
+    /// A `compile_only` request built successfully; no test cases were run
+    Compiled,
+    /// The problem has no test cases, so nothing was judged
+    NoTestCases,
     UnsupportedLanguage,
+    /// `source_filename` or `extra_headers` was rejected before compiling
+    InvalidRequest,
     EnvError,
 }
 
+/// Stable reason a judge call failed, for clients to branch on; `error` keeps the detail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// The request asks for something the judge can't do, e.g. `files` for a language
+    /// without project builds or a bad `source_filename`; rejected before compiling
+    InvalidRequest,
+    /// A test input file, `expected_regex`, weight or subtask's points was rejected before compiling
+    InvalidTestData,
+    /// The requested C/C++ toolchain, or the compiler or interpreter for the language, isn't
+    /// installed, or the compiler doesn't support the requested language standard
+    ToolchainMissing,
+    CompileError,
+    CompileTimeout,
+    /// The compiler hit its memory or CPU-time cap
+    CompileResourceLimit,
+    /// The problem's custom checker didn't compile
+    CheckerCompileError,
+    /// The problem's interactor didn't compile
+    InteractorCompileError,
+    /// A test case's program couldn't be started; that case is a `RuntimeError`
+    SpawnFailed,
+    /// The problem's checker failed on a test case; that case is a `JudgeError`
+    CheckerFailed,
+    /// The problem's interactor failed on a test case; that case is a `JudgeError`
+    InteractorFailed,
+    /// The judge itself failed (I/O error, cancelled or rejected request, ...); see `error`
+    InternalError,
+}
+
+/// Submission language. Parsed case-insensitively, accepting common aliases
+/// (`c++`, `python3`, ...); serialized as its lowercase name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", try_from = "String")]
+pub enum Language {
+    C,
+    Cpp,
+    Python,
+    Java,
+    Rust,
+}
+
+impl Language {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Python => "python",
+            Language::Java => "java",
+            Language::Rust => "rust",
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "c" => Ok(Language::C),
+            "cpp" | "c++" | "cxx" => Ok(Language::Cpp),
+            "python" | "python3" | "py" => Ok(Language::Python),
+            "java" => Ok(Language::Java),
+            "rust" | "rs" => Ok(Language::Rust),
+            _ => Err(anyhow::anyhow!("Unsupported language: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Language {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct NormalizationOptions {
     pub normalize_crlf: bool,
     pub ignore_extra_whitespace: bool,
+    /// Compare float tokens with this absolute-or-relative tolerance. An expected token is a
+    /// float if it is a finite number with a `.` or an exponent; integer and other tokens
+    /// must match exactly
+    #[serde(default)]
+    pub float_tolerance: Option<f64>,
+    /// Compare the flat sequence of whitespace-separated tokens, ignoring line structure
+    #[serde(default)]
+    pub token_mode: bool,
+    /// Lowercase both outputs (after trimming) before comparing
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// Drop a single final newline from both outputs, leaving other whitespace alone
+    #[serde(default)]
+    pub ignore_trailing_newline: bool,
+    /// Compare raw stdout bytes exactly against `expected_output`, skipping UTF-8 decoding and all other options.
+    /// Outputs that aren't UTF-8 can be given with a `Base64` or `Gzip` test case `encoding`
+    #[serde(default)]
+    pub binary_output: bool,
+    /// Compare the normalized lines as a multiset, for answers that may be printed in any order
+    #[serde(default)]
+    pub unordered_lines: bool,
 }
 
 /// Represents a test case for a problem
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestCase {
-    pub input: String,
+    pub input: TestCaseSource,
     pub expected_output: String,
+    /// Other outputs that are also correct, compared like `expected_output`
+    #[serde(default)]
+    pub accepted_outputs: Vec<String>,
+    /// Pattern the whole normalized output must match, used instead of `expected_output`
+    #[serde(default)]
+    pub expected_regex: Option<String>,
     pub is_hidden: bool,
+    /// Relative importance in the score (default 1.0)
+    #[serde(default)]
+    pub weight: Option<f64>,
+    /// Overrides `Problem::time_limit` for this case
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+    /// Overrides `Problem::memory_limit` for this case
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+    /// Generator seed, passed to the program and the checker as `JUDGE_SEED`
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// How the inline input and the expected/accepted outputs are encoded. The input must
+    /// decode to UTF-8, as must the outputs unless `binary_output` is set
+    #[serde(default)]
+    pub encoding: TestDataEncoding,
+}
+
+/// Encoding of a test case's inline data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestDataEncoding {
+    #[default]
+    Plain,
+    /// Base64 of gzip-compressed data; decompressed before judging
+    Gzip,
+    /// Base64 of the raw data, e.g. exact bytes for `binary_output`
+    Base64,
+}
+
+impl TestCase {
+    /// `expected_output` followed by the alternative `accepted_outputs`
+    pub fn expected_outputs(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.expected_output.as_str()).chain(self.accepted_outputs.iter().map(String::as_str))
+    }
+}
+
+/// Where a test case's input comes from: an inline string or `{"path": ...}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TestCaseSource {
+    Inline(String),
+    /// File relative to the judge's test-data root, streamed to stdin without loading it
+    File { path: PathBuf },
+}
+
+impl From<String> for TestCaseSource {
+    fn from(input: String) -> Self {
+        TestCaseSource::Inline(input)
+    }
+}
+
+impl From<&str> for TestCaseSource {
+    fn from(input: &str) -> Self {
+        TestCaseSource::Inline(input.to_string())
+    }
 }
 
 /// Represents a programming problem
@@ -32,9 +219,95 @@ pub struct Problem {
     pub description: String,
     pub difficulty: Difficulty,
     pub time_limit: u64, // in milliseconds
+    #[serde(default)]
+    pub time_limit_mode: TimeLimitMode,
+    /// Accepted runs that take longer than this (measured like `time_limit`) are flagged `slow`
+    #[serde(default)]
+    pub soft_time_limit_ms: Option<u64>,
     pub memory_limit: u64, // in MB
+    #[serde(default)]
+    pub memory_sampling: MemorySampling,
+    #[serde(default)]
+    pub io_mode: IoMode,
+    /// Stdout cap in bytes; defaults to 64 MB
+    #[serde(default)]
+    pub output_limit_bytes: Option<u64>,
     pub test_cases: Vec<TestCase>,
     pub tags: Vec<String>,
+    /// Special judge used instead of output comparison
+    #[serde(default)]
+    pub checker: Option<CheckerSpec>,
+    /// All-or-nothing groups of test cases; empty means per-test scoring
+    #[serde(default)]
+    pub subtasks: Vec<Subtask>,
+    /// Max processes/threads a submission may run at once; defaults to 16 (uncapped for Java)
+    #[serde(default)]
+    pub process_limit: Option<u64>,
+    /// Kill a run that goes this long without printing or using CPU (off by default; Linux only)
+    #[serde(default)]
+    pub idle_limit_ms: Option<u64>,
+    /// Let submissions open network connections (blocked by default)
+    #[serde(default)]
+    pub allow_network: bool,
+    /// Command-line arguments passed to the submission
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables for the submission; the host environment is not inherited (except `PATH`)
+    #[serde(default)]
+    pub env: Vec<(String, String)>,
+    /// Interactor for adaptive problems; wired to the solution's stdin/stdout
+    #[serde(default)]
+    pub interactor: Option<CodeFile>,
+}
+
+/// Group of test cases scored together: points are scaled by the lowest case score, so all
+/// of them need every case to pass
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subtask {
+    pub test_cases: Vec<usize>, // indices into Problem::test_cases
+    pub points: f64,
+}
+
+/// Source of a custom checker program (C++), see `checker` for the protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckerSpec {
+    pub source: String,
+    #[serde(default)]
+    pub time_limit_ms: Option<u64>,
+}
+
+/// Clock that `Problem::time_limit` is measured against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeLimitMode {
+    #[default]
+    Wall,
+    /// User + system CPU time; falls back to wall time where CPU time is unavailable
+    Cpu,
+}
+
+/// Where a submission reads its input and writes its answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IoMode {
+    #[default]
+    Stdio,
+    /// `input.txt` and `output.txt` in the run's working directory
+    Files,
+}
+
+/// How a run's peak memory is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemorySampling {
+    /// Poll the RSS of the whole process tree (forked workers included) every `interval_ms`
+    Poll { interval_ms: u64 },
+    /// Read the kernel's peak RSS once at exit (Linux; polls elsewhere). Cheaper and catches
+    /// short spikes, but only counts the main process and children it waited for.
+    PeakAtExit,
+}
+
+impl Default for MemorySampling {
+    fn default() -> Self {
+        MemorySampling::Poll { interval_ms: crate::executor::DEFAULT_SAMPLING_INTERVAL.as_millis() as u64 }
+    }
 }
 
 /// Difficulty levels for problems
@@ -50,19 +323,82 @@ pub enum Difficulty {
 pub struct ExecutionResult {
     pub success: bool,
     pub output: String,
+    /// Raw stdout; `output` is its lossy UTF-8 rendering. Not serialized.
+    #[serde(skip)]
+    pub output_bytes: Vec<u8>,
+    /// Why the run failed (a limit, the exit code or signal); `None` for a clean run
     pub error: Option<String>,
+    /// Everything the program wrote to stderr, whether or not it succeeded
+    #[serde(default)]
+    pub stderr: String,
     pub execution_time: u64, // in milliseconds
+    /// User + system CPU time in milliseconds, where the platform reports it
+    #[serde(default)]
+    pub cpu_time_ms: Option<u64>,
     pub memory_usage: u64, // in KB
 }
 
+/// Outcome of a single test case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestVerdict {
+    Accepted,
+    WrongAnswer,
+    /// Same tokens as the expected output, but laid out with different whitespace
+    PresentationError,
+    /// The checker accepted the output with a score strictly between 0 and 1
+    PartiallyCorrect,
+    TimeLimitExceeded,
+    /// Killed after `idle_limit_ms` without output or CPU use (e.g. blocked on a read)
+    IdlenessLimitExceeded,
+    RuntimeError,
+    MemoryLimitExceeded,
+    /// Stdout grew past the output limit
+    OutputLimitExceeded,
+    /// AddressSanitizer reported a memory error
+    SanitizerError,
+    /// Not run because judging stopped early
+    Skipped,
+    /// The checker or interactor failed (crashed, timed out, unknown exit code), so the
+    /// output wasn't judged
+    JudgeError,
+}
+
 /// Result of test case evaluation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestCaseResult {
     pub test_case_id: usize,
-    pub passed: bool,
+    pub passed: bool, // verdict == Accepted, kept for older clients
+    pub verdict: TestVerdict,
+    /// Fraction of the case's weight earned (0.0 to 1.0)
+    #[serde(default)]
+    pub points: f64,
+    /// Accepted, but over the problem's `soft_time_limit_ms`
+    #[serde(default)]
+    pub slow: bool,
     pub execution_result: ExecutionResult,
     pub expected_output: String,
     pub actual_output: String,
+    /// Comment printed by the custom checker, if one was used
+    #[serde(default)]
+    pub checker_message: Option<String>,
+    /// Index of the first mismatching token (token-based comparison only)
+    #[serde(default)]
+    pub first_diff_token: Option<usize>,
+    /// 1-based line where normalized expected and actual output first differ (wrong answers only)
+    #[serde(default)]
+    pub first_diff_line: Option<usize>,
+    /// Unified diff of normalized expected vs actual output for wrong answers
+    #[serde(default)]
+    pub diff: Option<String>,
+}
+
+/// Score obtained on one subtask
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubtaskResult {
+    pub subtask_id: usize,
+    pub passed: bool,
+    pub points: f64,
+    pub max_points: f64,
 }
 
 /// Overall submission result
@@ -71,23 +407,136 @@ pub struct SubmissionResult {
     pub problem_id: String,
     pub total_test_cases: usize,
     pub passed_test_cases: usize,
+    /// Split of the counts above into sample (not hidden) and hidden cases
+    #[serde(default)]
+    pub sample_test_cases: usize,
+    #[serde(default)]
+    pub sample_passed: usize,
+    #[serde(default)]
+    pub hidden_test_cases: usize,
+    #[serde(default)]
+    pub hidden_passed: usize,
     pub test_case_results: Vec<TestCaseResult>,
     pub compilation_successful: bool,
     pub compilation_error: Option<String>,
     pub total_execution_time: u64,
+    /// Highest `memory_usage` over all test cases, in KB
+    #[serde(default)]
+    pub peak_memory_kb: u64,
     pub score: f64, // percentage
+    #[serde(default)]
+    pub subtask_results: Vec<SubtaskResult>,
+    /// Wall time of the compile step; only the cache lookup when `compilation_cached` is set
     pub compile_time_ms: Option<u64>,
+    /// Time a `judge_batch` submission waited for a worker. Single `judge` calls start
+    /// right away (or are refused as busy), so for them this is always 0
+    #[serde(default)]
+    pub queue_time_ms: u64,
+    /// Time spent comparing outputs (comparator or checker), summed over the test cases
+    #[serde(default)]
+    pub comparison_time_ms: u64,
+    /// Wall time of the whole judge call after the queue, from preparing the test data to the result
+    #[serde(default)]
+    pub judge_time_ms: u64,
+    /// `judge_time_ms` minus compile, execution and comparison time: setup, process
+    /// spawning and bookkeeping. Clamped at zero, as parallel test cases overlap
+    #[serde(default)]
+    pub overhead_ms: u64,
+    /// The binary came from the compile cache instead of a fresh build
+    #[serde(default)]
+    pub compilation_cached: bool,
+    /// For cached binaries, how long the build that produced them took
+    #[serde(default)]
+    pub cached_build_time_ms: Option<u64>,
     pub executable_size_bytes: Option<u64>,
+    /// The `debug_build` binary, kept for a debugger until `Judge::gc` finds it unused for
+    /// `RetentionPolicy::debug_binaries`; identical builds share it
+    #[serde(default)]
+    pub debug_executable_path: Option<String>,
+    /// Compiler diagnostics from a successful build (also reported on cache hits)
+    #[serde(default)]
+    pub compile_warnings: Option<String>,
+}
+
+/// A named source file, for multi-file projects, headers and interactors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeFile {
+    pub filename: String,
+    pub content: String,
+}
+
+/// `filename` as a path inside the build directory; `..`, absolute paths and drive
+/// prefixes are rejected so a project can't write outside it
+pub(crate) fn relative_source_path(filename: &str) -> Result<PathBuf> {
+    let path = Path::new(filename);
+    let inside = path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if filename.is_empty() || !inside || path.components().all(|c| c == Component::CurDir) {
+        return Err(anyhow::anyhow!("Invalid file name: {:?} must be a relative path inside the project", filename));
+    }
+    Ok(path.to_path_buf())
 }
 
 /// Request to compile and run code
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JudgeRequest {
+    #[serde(default)]
     pub code: String,
     pub problem: Problem,
-    pub language: String, // "c", "cpp", etc.
+    pub language: Language,
     #[serde(default)]
     pub normalization: NormalizationOptions,
+    /// Multi-file project (C, C++ or Rust); takes precedence over `code` when present
+    #[serde(default)]
+    pub files: Option<Vec<CodeFile>>,
+    /// Header files (C/C++) written next to the single-file source so `#include "helper.h"`
+    /// resolves; they are never compiled on their own
+    #[serde(default)]
+    pub extra_headers: Vec<CodeFile>,
+    /// Skip the remaining test cases after the first one that fails
+    #[serde(default)]
+    pub stop_on_first_failure: bool,
+    /// Number of test cases run concurrently (default 1)
+    #[serde(default)]
+    pub max_parallel_tests: Option<usize>,
+    /// Maximum number of lines in a wrong-answer diff (default 200)
+    #[serde(default)]
+    pub max_diff_lines: Option<usize>,
+    /// Cut compiler errors and warnings to this many lines (unlimited by default)
+    #[serde(default)]
+    pub max_compile_output_lines: Option<usize>,
+    /// Compiler time limit; defaults to 10 s (15 s for multi-file projects)
+    #[serde(default)]
+    pub compile_timeout_ms: Option<u64>,
+    /// Largest accepted source in bytes; defaults to 256 KB
+    #[serde(default)]
+    pub max_source_bytes: Option<usize>,
+    /// Cap on the whole request's wall time, compilation included; cases still
+    /// running or not yet started when it passes are skipped
+    #[serde(default)]
+    pub total_time_limit_ms: Option<u64>,
+    /// Only compile the submission and report the build, without running any test case
+    #[serde(default)]
+    pub compile_only: bool,
+    /// Include hidden test cases' data (expected/actual output, stderr, diffs) in the response
+    #[serde(default)]
+    pub reveal_hidden: bool,
+    /// Contest feedback: hidden cases report only their verdict and points, without the
+    /// time, memory and limit messages that are otherwise kept (and are left out of the
+    /// totals); sample cases keep full detail. Ignored with `reveal_hidden`
+    #[serde(default)]
+    pub hidden_verdict_only: bool,
+    /// Over stdio, emit each test case result as its own event before the final response
+    #[serde(default)]
+    pub stream: bool,
+    /// Over stdio, emit a `progress` event (completed/total cases) as each test case finishes
+    #[serde(default)]
+    pub progress: bool,
+    /// Also return compiler errors and warnings as `JudgeResponse::diagnostics`
+    #[serde(default)]
+    pub structured_diagnostics: bool,
+    /// C/C++ compiler settings (`compile_flags`, `opt_level`, ...)
+    #[serde(flatten)]
+    pub compile_options: CompileOptions,
 }
 
 /// Response from judge
@@ -97,4 +546,43 @@ pub struct JudgeResponse {
     pub result: Option<SubmissionResult>,
     pub error: Option<String>,
     pub status: OverallStatus,
+    /// Machine-readable reason for a failure, alongside the human-readable `error`
+    #[serde(default)]
+    pub error_code: Option<ErrorCode>,
+    /// Raw compiler diagnostics when `error` is a friendlier rewrite of them
+    #[serde(default)]
+    pub compiler_output: Option<String>,
+    /// Compiler command line used for the submission, to reproduce the build locally
+    #[serde(default)]
+    pub compile_command: Option<String>,
+    /// Parsed compiler errors or warnings, with `structured_diagnostics`; the raw text
+    /// stays in `error`/`compile_warnings` for lines that couldn't be parsed
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    /// Name and version of the compiler that rejected the code, on `CompileError`
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    /// Line count of the compiler errors or warnings before `max_compile_output_lines`
+    /// cut them; unset when nothing was cut
+    #[serde(default)]
+    pub compile_output_lines: Option<usize>,
+}
+
+impl JudgeResponse {
+    /// A judgement that ended before producing a result: `status` and `code` say why,
+    /// `message` explains it to a person
+    pub fn failure(status: OverallStatus, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            result: None,
+            error: Some(message.into()),
+            status,
+            error_code: Some(code),
+            compiler_output: None,
+            compile_command: None,
+            diagnostics: Vec::new(),
+            toolchain: None,
+            compile_output_lines: None,
+        }
+    }
 }